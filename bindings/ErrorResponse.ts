@@ -1,4 +1,8 @@
 // This file was generated by [ts-rs](https://github.com/Aleph-Alpha/ts-rs). Do not edit this file manually.
 import type { ErrorReason } from "./ErrorReason";
 
-export type ErrorResponse = { reason: ErrorReason, message: string, timestamp: string, };
+export type ErrorResponse = { reason: ErrorReason, message: string, timestamp: string, 
+/**
+ * `X-Request-Id` of the failed request, for correlating with server logs
+ */
+request_id: string | null, };
@@ -0,0 +1,6 @@
+// This file was generated by [ts-rs](https://github.com/Aleph-Alpha/ts-rs). Do not edit this file manually.
+
+/**
+ * Maintenance mode, admin RPCs staying available in every mode so it can be lifted
+ */
+export type MaintenanceMode = "off" | "read_only" | "full";
@@ -12,12 +12,14 @@
 
 import type { ErrorReason } from "./ErrorReason.ts";
 import type { ErrorResponse } from "./ErrorResponse.ts";
+import type { MaintenanceMode } from "./MaintenanceMode.ts";
 import type { UserRegistration } from "./UserRegistration.ts";
 import type { Mutation } from "@qubit-rs/client";
 
 export type { ErrorReason } from "./ErrorReason.ts";
 export type { ErrorResponse } from "./ErrorResponse.ts";
+export type { MaintenanceMode } from "./MaintenanceMode.ts";
 export type { UserRegistration } from "./UserRegistration.ts";
 export type { Mutation } from "@qubit-rs/client";
 
-export type QubitServer = { register: Mutation<[user: UserRegistration, ], { Ok : string } | { Err : ErrorResponse }> };
\ No newline at end of file
+export type QubitServer = { register: Mutation<[user: UserRegistration, ], { Ok : string } | { Err : ErrorResponse }>, setup: Mutation<[token: string, user: UserRegistration, ], { Ok : string } | { Err : ErrorResponse }>, admin: { set_log_filter: Mutation<[filter: string, ], { Ok : null } | { Err : ErrorResponse }>, set_maintenance: Mutation<[mode: MaintenanceMode, ], { Ok : null } | { Err : ErrorResponse }> } };
\ No newline at end of file
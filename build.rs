@@ -1,28 +1,41 @@
-use std::{fs, path::Path, process::Command};
+use std::{fs, io::Write, path::Path, process::Command};
 
 const FRONTEND_BUILD_DIR: &str = "dist";
-const DB_PATH: &str = "data/breezi.db";
+/// Extensions of text-like assets worth precompressing, others (images, fonts) are already compressed.
+/// HTML is left out as `Spa` rebases it per deployment, compressing it on the fly.
+const COMPRESSIBLE_EXTENSIONS: [&str; 9] = ["js", "mjs", "css", "svg", "json", "txt", "xml", "wasm", "map"];
 
 fn main() {
-    create_database();
-    migrate_database();
+    // `sqlx::migrate!` embeds the migrations, queries are checked against `.sqlx` offline data
+    println!("cargo:rerun-if-changed=build.rs");
+    println!("cargo:rerun-if-changed=migrations");
 
     let is_release = std::env::var("PROFILE").is_ok_and(|v| v == "release");
     let is_frontend_built = std::fs::exists(FRONTEND_BUILD_DIR).is_ok_and(|v| v);
 
-    match (is_release, is_frontend_built) {
+    let is_building = match (is_release, is_frontend_built) {
         (true, true) => {
             println!("cargo:warning=Release build is forcing frontend rebuild");
             std::fs::remove_dir_all(FRONTEND_BUILD_DIR).expect("deletion of frontend build failed");
+            true
         }
         (false, true) => {
             println!("cargo:warning=Debug build is re-using frontend build");
-            return;
+            false
+        }
+        _ => {
+            println!("cargo:warning=Building frontend");
+            true
         }
-        _ => println!("cargo:warning=Building frontend"),
+    };
+
+    if is_building {
+        build_frontend();
     }
+    precompress_frontend(Path::new(FRONTEND_BUILD_DIR));
+}
 
-    // Run the build command
+fn build_frontend() {
     let status = Command::new(js_package_manager())
         .arg("run")
         .arg("build")
@@ -34,51 +47,53 @@ fn main() {
     }
 }
 
-fn js_package_manager() -> &'static str {
-    let manager = ["pnpm", "bun", "deno", "yarn", "npm"]
-        .iter()
-        .find_map(|manager| Command::new(manager).arg("--version").output().map(|_| manager).ok())
-        .expect("no JavaScript package manager installation found");
-    println!("cargo:warning=Using {manager} as package manager");
-    manager
-}
+/// Writes `.br` and `.gz` variants next to each compressible asset, served by `Spa` to accepting clients
+fn precompress_frontend(dir: &Path) {
+    for entry in fs::read_dir(dir).expect("reading frontend build failed") {
+        let path = entry.expect("reading frontend build entry failed").path();
+        if path.is_dir() {
+            precompress_frontend(&path);
+            continue;
+        }
 
-fn create_database() {
-    // Create parent directory if it doesn't exist
-    if let Some(parent) = Path::new(DB_PATH).parent() {
-        fs::create_dir_all(parent).unwrap();
-    }
+        let is_compressible = path
+            .extension()
+            .and_then(|ext| ext.to_str())
+            .is_some_and(|ext| COMPRESSIBLE_EXTENSIONS.contains(&ext));
+        if !is_compressible {
+            continue;
+        }
 
-    // Create empty SQLite database file if it doesn't exist
-    if !Path::new(DB_PATH).exists() {
-        // SQLite will create the file if you open a connection
-        let _ = rusqlite::Connection::open(DB_PATH).unwrap();
-        println!("cargo:warning=Debug Build creating {DB_PATH} ");
-    } else {
-        println!("cargo:warning=Debug Build found existing database");
-    }
+        let data = fs::read(&path).expect("reading frontend asset failed");
 
-    // Tell Cargo to rerun build.rs if this script or migrations changes
-    println!("cargo:rerun-if-changed=build.rs");
-    println!("cargo:rerun-if-changed=migrations");
-}
+        let mut brotli = Vec::new();
+        {
+            let mut writer = brotli::CompressorWriter::new(&mut brotli, 4096, 11, 22);
+            writer.write_all(&data).expect("brotli compression failed");
+        }
+        write_if_smaller(&path, "br", &data, &brotli);
 
-fn migrate_database() {
-    // Ensure sqlx-cli is installed
-    let status = Command::new("cargo")
-        .args(["install", "sqlx-cli"])
-        .status()
-        .expect("failed to run cargo install sqlx-cli");
-    if !status.success() {
-        panic!("Failed to install sqlx-cli");
+        let mut gzip = flate2::write::GzEncoder::new(Vec::new(), flate2::Compression::best());
+        gzip.write_all(&data).expect("gzip compression failed");
+        write_if_smaller(&path, "gz", &data, &gzip.finish().expect("gzip compression failed"));
     }
+}
 
-    // Run sqlx migrations
-    let status = Command::new("sqlx")
-        .args(["migrate", "run", "--database-url", &format!("sqlite://{DB_PATH}")])
-        .status()
-        .expect("failed to run sqlx migrate run");
-    if !status.success() {
-        panic!("sqlx migrate run failed");
+fn write_if_smaller(path: &Path, extension: &str, original: &[u8], compressed: &[u8]) {
+    let mut variant = path.as_os_str().to_owned();
+    variant.push(format!(".{extension}"));
+    if compressed.len() < original.len() {
+        fs::write(&variant, compressed).expect("writing precompressed asset failed");
+    } else {
+        let _ = fs::remove_file(&variant);
     }
 }
+
+fn js_package_manager() -> &'static str {
+    let manager = ["pnpm", "bun", "deno", "yarn", "npm"]
+        .iter()
+        .find_map(|manager| Command::new(manager).arg("--version").output().map(|_| manager).ok())
+        .expect("no JavaScript package manager installation found");
+    println!("cargo:warning=Using {manager} as package manager");
+    manager
+}
@@ -0,0 +1,2 @@
+-- Roles of users, managed with `breezi user set-role`
+ALTER TABLE user ADD COLUMN role TEXT NOT NULL DEFAULT 'user';
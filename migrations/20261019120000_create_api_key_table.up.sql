@@ -0,0 +1,7 @@
+-- API keys of users, issued with `breezi user issue-api-key` and stored as SHA-256 hashes
+CREATE TABLE api_key (
+    id TEXT PRIMARY KEY NOT NULL,
+    user_id TEXT NOT NULL REFERENCES user(id) ON DELETE CASCADE,
+    key_hash TEXT NOT NULL UNIQUE,
+    created_at TEXT NOT NULL DEFAULT CURRENT_TIMESTAMP
+);
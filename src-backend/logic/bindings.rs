@@ -1,22 +1,33 @@
-use crate::{model::Config, routes::Routes};
+use crate::{logic::config_schema, model::Config, routes::Routes};
 use schemars::schema_for;
 use std::{fs::write, path::Path};
 use tracing::info;
 
 pub fn generate_all_bindings(config: &Config) -> crate::Result {
     if config.bindings_generate {
-        info!("Generating bindings");
-        Routes::gen_bindings(&config.bindings_dir);
-        gen_validations(&config.bindings_dir)?;
+        generate_bindings(&config.bindings_dir)?;
     }
 
     Ok(())
 }
 
+/// Writes the RPC bindings and validation schemas, regardless of `bindings_generate`
+pub fn generate_bindings(bindings_dir: &Path) -> crate::Result {
+    info!("Generating bindings");
+    Routes::gen_bindings(bindings_dir);
+    gen_validations(bindings_dir)?;
+
+    Ok(())
+}
+
 fn gen_validations(bindings_dir: &Path) -> crate::Result {
     let schema = schema_for!(crate::model::UserAll);
 
     write(bindings_dir.join("user.schema.json"), serde_json::to_string_pretty(&schema)?)?;
+    write(
+        bindings_dir.join("config.schema.json"),
+        serde_json::to_string_pretty(&config_schema()?)?,
+    )?;
 
     Ok(())
 }
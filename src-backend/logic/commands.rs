@@ -0,0 +1,304 @@
+use crate::{
+    logic::{
+        CheckStatus, Fixtures, backup_database, connect_database, doctor, generate_bindings, init_config, migrate_down,
+        migrate_up, migration_status, request_maintenance, restore_database, setup_database, vacuum_database,
+    },
+    model::{
+        ApiKey, BindingsCommand, Command, Config, ConfigCommand, DbCommand, MigrateCommand, Secret, UserAll, UserCommand,
+        UserListing, UserPassword, UserRegistration,
+    },
+};
+use color_eyre::eyre::{bail, eyre};
+use std::io::BufRead;
+use validator::Validate;
+
+/// Runs an operational command instead of the server
+pub async fn run_command(config: &Config, command: Command) -> crate::Result {
+    match command {
+        Command::Serve => bail!("serve is run by main"),
+        Command::Migrate(command) => migrate(config, command).await,
+        Command::Bindings(BindingsCommand::Generate) => generate_bindings(&config.bindings_dir),
+        Command::User(command) => user(config, command).await,
+        Command::Db(command) => db(config, command).await,
+        Command::Seed { set } => seed(config, set).await,
+        Command::Maintenance { mode } => request_maintenance(config, mode).await,
+        Command::Config(ConfigCommand::Print) => {
+            print!("{}", serde_yaml::to_string(config)?);
+            Ok(())
+        }
+        Command::Config(ConfigCommand::Check) => {
+            println!("Configuration is valid");
+            Ok(())
+        }
+        Command::Doctor => {
+            let checks = doctor(config).await;
+            for check in &checks {
+                println!("{check}");
+            }
+            match checks.iter().filter(|check| check.status == CheckStatus::Fail).count() {
+                0 => Ok(()),
+                failed => bail!("{failed} checks failed"),
+            }
+        }
+        Command::Config(ConfigCommand::Init { path, force }) => {
+            init_config(config, path.as_deref().unwrap_or(&config.config_path), force)
+        }
+    }
+}
+
+async fn migrate(config: &Config, command: MigrateCommand) -> crate::Result {
+    let pool = connect_database(config).await?;
+    match command {
+        MigrateCommand::Up => {
+            migrate_up(&pool).await?;
+            println!("Database is up to date");
+        }
+        MigrateCommand::Down => match migrate_down(&pool).await? {
+            Some(reverted) => println!("Reverted {} {}", reverted.version, reverted.description),
+            None => println!("No migration is applied"),
+        },
+        MigrateCommand::Status => {
+            for migration in migration_status(&pool).await? {
+                let state = if migration.is_applied { "applied" } else { "pending" };
+                let reversible = if migration.is_reversible { "" } else { " (irreversible)" };
+                println!("{:<16} {:<8} {}{reversible}", migration.version, state, migration.description);
+            }
+        }
+    }
+    pool.close().await;
+    Ok(())
+}
+
+async fn user(config: &Config, command: UserCommand) -> crate::Result {
+    let pool = setup_database(config).await?;
+    match command {
+        UserCommand::Create {
+            username,
+            email,
+            role,
+            password,
+        } => {
+            let user = UserRegistration {
+                username,
+                password: password_or_stdin(password)?.expose().to_string(),
+                email,
+            };
+            user.validate()?;
+            let mut tx = pool.begin().await?;
+            let id = user.insert(&mut *tx).await?;
+            UserAll::set_role(&mut *tx, &user.username, role).await?;
+            tx.commit().await?;
+            println!("Created {role} {} ({id})", user.username);
+        }
+        UserCommand::SetPassword { username, password } => {
+            let password = UserPassword {
+                password: password_or_stdin(password)?.expose().to_string(),
+            };
+            password.validate()?;
+            if !UserAll::set_password(&pool, &username, &password.password).await? {
+                bail!("No user named {username}");
+            }
+            println!("Password of {username} changed");
+        }
+        UserCommand::SetRole { username, role } => {
+            if !UserAll::set_role(&pool, &username, role).await? {
+                bail!("No user named {username}");
+            }
+            println!("{username} is now {role}");
+        }
+        UserCommand::IssueApiKey { username } => {
+            let Some(key) = ApiKey::issue(&pool, &username).await? else {
+                bail!("No user named {username}");
+            };
+            println!("API key of {username}, shown only now: {}", key.expose());
+        }
+        UserCommand::List => {
+            for user in UserListing::all(&pool).await? {
+                println!("{:<36} {:<32} {:<6} {}", user.id, user.username, user.role, user.email);
+            }
+        }
+    }
+    pool.close().await;
+    Ok(())
+}
+
+async fn db(config: &Config, command: DbCommand) -> crate::Result {
+    match command {
+        DbCommand::Backup { path } => {
+            let pool = setup_database(config).await?;
+            backup_database(&pool, &path).await?;
+            pool.close().await;
+            println!("Backed up {} to {}", config.database.display(), path.display());
+        }
+        DbCommand::Restore { path } => restore_database(config, &path).await?,
+        DbCommand::Vacuum => {
+            let pool = setup_database(config).await?;
+            vacuum_database(&pool).await?;
+            pool.close().await;
+            println!("Vacuumed {}", config.database.display());
+        }
+    }
+    Ok(())
+}
+
+async fn seed(config: &Config, set: Option<String>) -> crate::Result {
+    let set = set.or_else(|| config.config_profile.clone()).unwrap_or_else(|| "dev".into());
+    let fixtures = Fixtures::load(&config.fixtures_dir.join(&set))?;
+    let pool = setup_database(config).await?;
+    let report = fixtures.seed(&pool).await?;
+    pool.close().await;
+    println!(
+        "Seeded fixture set {set}: {} inserted, {} already present",
+        report.inserted, report.skipped
+    );
+    Ok(())
+}
+
+/// The given password, else the first line of stdin
+fn password_or_stdin(password: Option<Secret>) -> crate::Result<Secret> {
+    if let Some(password) = password {
+        return Ok(password);
+    }
+    eprintln!("Password (read from stdin):");
+    let line = std::io::stdin()
+        .lock()
+        .lines()
+        .next()
+        .ok_or_else(|| eyre!("No password given on stdin"))??;
+    Ok(Secret::from(line))
+}
+
+#[cfg(test)]
+mod test {
+    use clap::Parser;
+    use std::path::PathBuf;
+    use uuid::Uuid;
+
+    use super::run_command;
+    use crate::{
+        logic::{connect_database, migration_status, setup_database},
+        model::{Command, Config, DbCommand, MigrateCommand, UserCommand, UserListing, UserRole},
+    };
+
+    /// Config of a database in a fresh temporary directory, removed by the caller
+    fn temp_config() -> (Config, PathBuf) {
+        let dir = std::env::temp_dir().join(format!("breezi-{}", Uuid::new_v4()));
+        let database = dir.join("breezi.db");
+        (
+            Config::parse_from([env!("CARGO_PKG_NAME"), "--database", database.to_str().unwrap()]),
+            dir,
+        )
+    }
+
+    fn create_alice() -> Command {
+        Command::User(UserCommand::Create {
+            username: "alice".into(),
+            email: "alice@example.com".into(),
+            role: UserRole::User,
+            password: Some("hunter22".parse().unwrap()),
+        })
+    }
+
+    fn set_role(username: &str, role: UserRole) -> Command {
+        Command::User(UserCommand::SetRole {
+            username: username.into(),
+            role,
+        })
+    }
+
+    async fn applied(config: &Config) -> Vec<bool> {
+        let pool = connect_database(config).await.unwrap();
+        let status = migration_status(&pool).await.unwrap();
+        pool.close().await;
+        status.into_iter().map(|migration| migration.is_applied).collect()
+    }
+
+    async fn roles(config: &Config) -> Vec<(String, UserRole)> {
+        let pool = setup_database(config).await.unwrap();
+        let users = UserListing::all(&pool).await.unwrap();
+        pool.close().await;
+        users.into_iter().map(|user| (user.username, user.role)).collect()
+    }
+
+    #[tokio::test]
+    async fn run_command_given_backup_then_restore_brings_it_back() {
+        // Given
+        let (config, dir) = temp_config();
+        let backup = dir.join("backup.db");
+        run_command(&config, create_alice()).await.unwrap();
+        run_command(&config, Command::Db(DbCommand::Backup { path: backup.clone() }))
+            .await
+            .unwrap();
+        run_command(&config, set_role("alice", UserRole::Admin)).await.unwrap();
+
+        // When
+        run_command(&config, Command::Db(DbCommand::Restore { path: backup }))
+            .await
+            .unwrap();
+
+        // Then
+        assert_eq!(roles(&config).await, vec![("alice".to_string(), UserRole::User)]);
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[tokio::test]
+    async fn run_command_given_open_database_then_restore_refused() {
+        // Given
+        let (config, dir) = temp_config();
+        let backup = dir.join("backup.db");
+        run_command(&config, create_alice()).await.unwrap();
+        run_command(&config, Command::Db(DbCommand::Backup { path: backup.clone() }))
+            .await
+            .unwrap();
+        let server = setup_database(&config).await.unwrap();
+
+        // When
+        let result = run_command(&config, Command::Db(DbCommand::Restore { path: backup })).await;
+
+        // Then
+        assert!(result.unwrap_err().to_string().contains("in use"));
+        server.close().await;
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[tokio::test]
+    async fn run_command_given_migrate_down_then_up_reapplies_latest() {
+        // Given
+        let (config, dir) = temp_config();
+        run_command(&config, Command::Migrate(MigrateCommand::Up)).await.unwrap();
+
+        // When
+        run_command(&config, Command::Migrate(MigrateCommand::Down)).await.unwrap();
+        let after_down = applied(&config).await;
+        run_command(&config, Command::Migrate(MigrateCommand::Up)).await.unwrap();
+        let after_up = applied(&config).await;
+
+        // Then
+        let (latest, earlier) = after_down.split_last().unwrap();
+        assert!(!latest);
+        assert!(earlier.iter().all(|is_applied| *is_applied));
+        assert!(after_up.iter().all(|is_applied| *is_applied));
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[tokio::test]
+    async fn run_command_given_missing_user_or_short_password_then_error() {
+        // Given
+        let (config, dir) = temp_config();
+        run_command(&config, create_alice()).await.unwrap();
+        let short_password = Command::User(UserCommand::SetPassword {
+            username: "alice".into(),
+            password: Some("abc".parse().unwrap()),
+        });
+
+        // When
+        let missing = run_command(&config, set_role("bob", UserRole::Admin)).await;
+        let short = run_command(&config, short_password).await;
+
+        // Then
+        assert_eq!(missing.unwrap_err().to_string(), "No user named bob");
+        assert!(short.unwrap_err().to_string().contains("password"));
+        assert_eq!(roles(&config).await, vec![("alice".to_string(), UserRole::User)]);
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+}
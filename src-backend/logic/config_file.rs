@@ -0,0 +1,124 @@
+use crate::model::Config;
+use clap::CommandFactory;
+use color_eyre::eyre::bail;
+use schemars::{Schema, schema_for};
+use serde_json::{Value, json};
+use std::{fmt::Write as _, path::Path};
+use tracing::info;
+
+/// Settings selecting the config file, meaningless inside it
+const COMMAND_LINE_ONLY: [&str; 2] = ["config_path", "config_profile"];
+
+/// Commented YAML config of every setting at its default, documented from the [`Config`] doc comments
+pub fn config_template(schema: Option<&Path>) -> crate::Result<String> {
+    let defaults = serde_json::to_value(Config::defaults()?)?;
+    let mut template = String::new();
+    if let Some(schema) = schema {
+        writeln!(template, "# yaml-language-server: $schema={}", schema.display())?;
+    }
+    writeln!(
+        template,
+        "# Generated by `config init`, settings left commented out are unset by default."
+    )?;
+    writeln!(template, "# Environment variables and command line flags override this file.")?;
+
+    for arg in <Config as CommandFactory>::command().get_arguments() {
+        let key = arg.get_id().as_str();
+        let Some(value) = defaults.get(key).filter(|_| !COMMAND_LINE_ONLY.contains(&key)) else {
+            continue;
+        };
+
+        writeln!(template)?;
+        let help = arg
+            .get_long_help()
+            .or(arg.get_help())
+            .map(ToString::to_string)
+            .unwrap_or_default();
+        for line in help.lines() {
+            writeln!(template, "# {line}")?;
+        }
+        // Files are read by serde, which spells the kebab-case flag values in snake_case
+        let choices: Vec<_> = arg
+            .get_possible_values()
+            .iter()
+            .map(|value| value.get_name().replace('-', "_"))
+            .collect();
+        if !value.is_boolean() && !choices.is_empty() {
+            writeln!(template, "# One of: {}", choices.join(", "))?;
+        }
+        if let Some(env) = arg.get_env() {
+            writeln!(template, "# Env: {}", env.to_string_lossy())?;
+        }
+
+        match value {
+            Value::Null => writeln!(template, "# {key}:")?,
+            Value::Array(values) if values.is_empty() => writeln!(template, "# {key}: []")?,
+            value => template.push_str(&serde_yaml::to_string(&json!({ key: value }))?),
+        }
+    }
+    Ok(template)
+}
+
+/// Writes the config template to `path`, refusing to replace an existing file unless `force`
+pub fn init_config(config: &Config, path: &Path, force: bool) -> crate::Result {
+    if path.exists() && !force {
+        bail!("{} already exists, pass --force to overwrite it", path.display());
+    }
+
+    // Editors resolve the schema relative to the config file, only known when both share the working directory
+    let is_in_working_dir = path
+        .parent()
+        .is_none_or(|dir| dir.as_os_str().is_empty() || dir == Path::new("."));
+    let schema = config.bindings_dir.join("config.schema.json");
+    let schema = (is_in_working_dir && config.bindings_dir.is_relative()).then_some(schema.as_path());
+
+    if let Some(dir) = path.parent().filter(|dir| !dir.as_os_str().is_empty()) {
+        std::fs::create_dir_all(dir)?;
+    }
+    std::fs::write(path, config_template(schema)?)?;
+    info!("Wrote config template to {}", path.display());
+    Ok(())
+}
+
+/// JSON schema of config files, every setting optional and documented with its default
+pub fn config_schema() -> crate::Result<Schema> {
+    let defaults = serde_json::to_value(Config::defaults()?)?;
+    let mut schema = schema_for!(Config);
+    schema.remove("required");
+    if let Some(Value::Object(properties)) = schema.get_mut("properties") {
+        for key in COMMAND_LINE_ONLY {
+            properties.remove(key);
+        }
+        for (key, property) in properties.iter_mut() {
+            let default = defaults.get(key).filter(|default| !default.is_null());
+            if let (Some(property), Some(default)) = (property.as_object_mut(), default) {
+                property.insert("default".into(), default.clone());
+            }
+        }
+    }
+    Ok(schema)
+}
+
+#[cfg(test)]
+mod test {
+    use serde_yaml::Value;
+
+    use super::config_template;
+
+    #[test]
+    fn config_template_given_defaults_then_parses_to_defaults() {
+        // Given
+        let template = config_template(None).unwrap();
+
+        // When
+        let parsed: Value = serde_yaml::from_str(&template).unwrap();
+
+        // Then
+        assert_eq!(parsed["server_port"], Value::from(8080));
+        assert_eq!(parsed["log_format"], Value::from("full"));
+        assert!(parsed.get("admin_token").is_none());
+        assert!(template.contains("# admin_token:\n"));
+        assert!(!template.contains("config_path"));
+        assert!(template.contains("# Server host binding port\n# Env: SERVER_PORT\nserver_port: 8080\n"));
+    }
+}
@@ -0,0 +1,414 @@
+use crate::{
+    logic::{is_dir_writable, migration_status, unknown_migrations},
+    model::Config,
+    routes::Routes,
+};
+use sqlx::{SqlitePool, sqlite::SqliteConnectOptions};
+use std::{
+    fmt::{self, Display},
+    fs::OpenOptions,
+    io::ErrorKind,
+    net::{IpAddr, SocketAddr},
+    path::Path,
+};
+use tokio::net::TcpListener;
+use validator::Validate;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum CheckStatus {
+    Pass,
+    Warn,
+    Fail,
+}
+
+/// Outcome of a single `doctor` check
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Check {
+    pub name: &'static str,
+    pub status: CheckStatus,
+    pub message: String,
+}
+
+impl Check {
+    fn pass(name: &'static str, message: impl Into<String>) -> Self {
+        Self {
+            name,
+            status: CheckStatus::Pass,
+            message: message.into(),
+        }
+    }
+
+    fn warn(name: &'static str, message: impl Into<String>) -> Self {
+        Self {
+            name,
+            status: CheckStatus::Warn,
+            message: message.into(),
+        }
+    }
+
+    fn fail(name: &'static str, message: impl Into<String>) -> Self {
+        Self {
+            name,
+            status: CheckStatus::Fail,
+            message: message.into(),
+        }
+    }
+}
+
+impl Display for Check {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let status = match self.status {
+            CheckStatus::Pass => "pass",
+            CheckStatus::Warn => "warn",
+            CheckStatus::Fail => "FAIL",
+        };
+        write!(f, "[{status}] {:<10} {}", self.name, self.message)
+    }
+}
+
+/// Checks the deployment without starting the server: config, database, embedded SPA and listeners
+pub async fn doctor(config: &Config) -> Vec<Check> {
+    let mut checks = check_config(config);
+    checks.extend(check_database(config).await);
+    checks.push(match Routes::is_spa_embedded() {
+        true => Check::pass("spa", "index.html is embedded"),
+        false => Check::fail("spa", "index.html is not embedded, build the frontend before the backend"),
+    });
+    checks.extend(check_listeners(config).await);
+    checks.push(check_tls(config));
+    checks
+}
+
+fn check_config(config: &Config) -> Vec<Check> {
+    let mut checks = match config.validate() {
+        Ok(()) => vec![Check::pass("config", "configuration is valid")],
+        Err(errors) => errors
+            .field_errors()
+            .into_iter()
+            .flat_map(|(field, errors)| errors.iter().map(move |err| (field.clone(), err)))
+            .map(|(field, err)| {
+                let field = if field == "__all__" {
+                    err.code.to_string()
+                } else {
+                    field.to_string()
+                };
+                Check::fail("config", format!("{field}: {}", err.message.as_deref().unwrap_or(&err.code)))
+            })
+            .collect(),
+    };
+
+    if config.admin_token.is_none() {
+        checks.push(Check::warn(
+            "config",
+            "admin_token is unset, admin RPCs and `maintenance` are disabled",
+        ));
+    }
+    if config.server_dev_proxy.is_some() {
+        checks.push(Check::warn(
+            "config",
+            "server_dev_proxy is set, the embedded SPA is not served",
+        ));
+    }
+    if config.metrics_enabled && config.metrics_port.is_none() && !config.server_host.is_loopback() {
+        checks.push(Check::warn(
+            "config",
+            "/metrics is public on the server port, set metrics_port to serve it separately",
+        ));
+    }
+    checks
+}
+
+async fn check_database(config: &Config) -> Vec<Check> {
+    let database = &config.database;
+    let dir = database
+        .parent()
+        .filter(|dir| !dir.as_os_str().is_empty())
+        .unwrap_or(Path::new("."));
+    if !database.exists() {
+        return vec![match is_dir_writable(dir) {
+            true => Check::warn(
+                "database",
+                format!("{} doesn't exist yet, it is created on start", database.display()),
+            ),
+            false => Check::fail(
+                "database",
+                format!("{} doesn't exist and {} is not writable", database.display(), dir.display()),
+            ),
+        }];
+    }
+
+    let mut checks = Vec::new();
+    let is_writable = OpenOptions::new().append(true).open(database).is_ok();
+    checks.push(match (is_writable, is_dir_writable(dir)) {
+        (true, true) => Check::pass("database", format!("{} and its directory are writable", database.display())),
+        (false, _) => Check::fail("database", format!("{} is not writable", database.display())),
+        // SQLite creates its journal and write-ahead log next to the database
+        (true, false) => Check::fail(
+            "database",
+            format!("{} is not writable, SQLite can't write its journal", dir.display()),
+        ),
+    });
+
+    // Read-only, so diagnosing never migrates or otherwise changes the database
+    let options = SqliteConnectOptions::new().filename(database).read_only(true);
+    let pool = match SqlitePool::connect_with(options).await {
+        Ok(pool) => pool,
+        Err(err) => {
+            checks.push(Check::fail("database", format!("can't open {}: {err}", database.display())));
+            return checks;
+        }
+    };
+    checks.push(check_migrations(&pool).await);
+    checks.push(check_pragma(&pool, "integrity", "integrity_check").await);
+    checks.push(check_pragma(&pool, "foreign_keys", "foreign_key_check").await);
+    pool.close().await;
+    checks
+}
+
+async fn check_migrations(pool: &SqlitePool) -> Check {
+    let (migrations, unknown) = match (migration_status(pool).await, unknown_migrations(pool).await) {
+        (Ok(migrations), Ok(unknown)) => (migrations, unknown),
+        (Err(err), _) | (_, Err(err)) => return Check::fail("migrations", format!("can't read migration state: {err}")),
+    };
+    let pending = migrations.iter().filter(|migration| !migration.is_applied).count();
+    match (pending, unknown.as_slice()) {
+        (_, [_, ..]) => Check::fail(
+            "migrations",
+            format!("applied migrations {unknown:?} are unknown to this build, the database is newer than the binary"),
+        ),
+        (0, _) => Check::pass("migrations", format!("all {} migrations applied", migrations.len())),
+        (pending, _) => Check::warn(
+            "migrations",
+            format!("{pending} pending, applied on start or by `migrate up`"),
+        ),
+    }
+}
+
+/// Runs a checking pragma, which reports nothing but `ok` when the database is sound
+async fn check_pragma(pool: &SqlitePool, name: &'static str, pragma: &str) -> Check {
+    match sqlx::query_scalar::<_, String>(&format!("PRAGMA {pragma}"))
+        .fetch_all(pool)
+        .await
+    {
+        Ok(rows) if rows.iter().all(|row| row == "ok") => Check::pass(name, format!("{pragma} passed")),
+        Ok(rows) => Check::fail(name, format!("{pragma} reported {}", rows.join("; "))),
+        Err(err) => Check::fail(name, format!("{pragma} failed: {err}")),
+    }
+}
+
+async fn check_listeners(config: &Config) -> Vec<Check> {
+    let mut checks = Vec::new();
+    if config.server_tcp {
+        checks.push(check_port("port", config.server_host, config.server_port).await);
+    }
+    if let Some(port) = config.metrics_port.filter(|_| config.metrics_enabled) {
+        checks.push(check_port("port", config.server_host, port).await);
+    }
+    if let Some(socket) = &config.server_unix_socket {
+        let dir = socket
+            .parent()
+            .filter(|dir| !dir.as_os_str().is_empty())
+            .unwrap_or(Path::new("."));
+        checks.push(match (socket.exists(), is_dir_writable(dir)) {
+            (_, false) => Check::fail("socket", format!("{} is not writable", dir.display())),
+            (true, true) => Check::warn("socket", format!("{} exists, it is replaced on start", socket.display())),
+            (false, true) => Check::pass("socket", format!("{} can be created", socket.display())),
+        });
+    }
+    if checks.is_empty() {
+        checks.push(Check::warn(
+            "listeners",
+            "no TCP or unix listener, only systemd sockets can serve",
+        ));
+    }
+    checks
+}
+
+async fn check_port(name: &'static str, host: IpAddr, port: u16) -> Check {
+    let addr = SocketAddr::from((host, port));
+    match TcpListener::bind(addr).await {
+        Ok(_) => Check::pass(name, format!("{addr} is free")),
+        Err(err) if err.kind() == ErrorKind::AddrInUse => {
+            Check::fail(name, format!("{addr} is in use, is the server already running?"))
+        }
+        Err(err) => Check::fail(name, format!("can't bind {addr}: {err}")),
+    }
+}
+
+/// Reads the configured certificate and key, else checks plain HTTP is left to a reverse proxy
+fn check_tls(config: &Config) -> Check {
+    if let Some((cert, key)) = config.tls_files() {
+        let unreadable = [cert, key].into_iter().find_map(|path| match std::fs::read(path) {
+            Ok(pem) if pem.is_empty() => Some(format!("{} is empty", path.display())),
+            Ok(_) => None,
+            Err(err) => Some(format!("can't read {}: {err}", path.display())),
+        });
+        return match unreadable {
+            Some(reason) => Check::fail("tls", reason),
+            None => Check::pass("tls", format!("{} and {} are readable", cert.display(), key.display())),
+        };
+    }
+    match config.server_tcp && !config.server_host.is_loopback() && config.server_trusted_proxies.is_empty() {
+        true => Check::warn(
+            "tls",
+            format!(
+                "serving plain HTTP on {}, terminate TLS at a reverse proxy",
+                config.server_host
+            ),
+        ),
+        false => Check::pass("tls", "left to the reverse proxy"),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use clap::Parser;
+    use std::{
+        net::{IpAddr, Ipv4Addr},
+        path::PathBuf,
+    };
+    use tokio::net::TcpListener;
+    use uuid::Uuid;
+
+    use super::{Check, CheckStatus, check_config, check_database, check_port, check_tls};
+    use crate::{
+        logic::{connect_database, setup_database},
+        model::Config,
+    };
+
+    /// Config of a database in a fresh temporary directory, removed by the caller
+    fn temp_config() -> (Config, PathBuf) {
+        let dir = std::env::temp_dir().join(format!("breezi-{}", Uuid::new_v4()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let database = dir.join("breezi.db");
+        (
+            Config::parse_from([env!("CARGO_PKG_NAME"), "--database", database.to_str().unwrap()]),
+            dir,
+        )
+    }
+
+    fn find<'a>(checks: &'a [Check], name: &str) -> &'a Check {
+        checks.iter().find(|check| check.name == name).unwrap()
+    }
+
+    #[tokio::test]
+    async fn check_database_given_missing_file_then_warn_created_on_start() {
+        // Given
+        let (config, dir) = temp_config();
+
+        // When
+        let checks = check_database(&config).await;
+
+        // Then
+        assert_eq!(checks.len(), 1);
+        assert_eq!(checks[0].status, CheckStatus::Warn);
+        assert!(checks[0].message.contains("created on start"));
+        assert!(!config.database.exists());
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[tokio::test]
+    async fn check_database_given_unmigrated_database_then_all_pending_and_left_untouched() {
+        // Given
+        let (config, dir) = temp_config();
+        connect_database(&config).await.unwrap().close().await;
+
+        // When
+        let checks = check_database(&config).await;
+
+        // Then
+        let migrations = find(&checks, "migrations");
+        assert_eq!(migrations.status, CheckStatus::Warn);
+        assert!(migrations.message.contains("pending"));
+        let pool = connect_database(&config).await.unwrap();
+        let tables: Vec<String> = sqlx::query_scalar("SELECT name FROM sqlite_master WHERE type = 'table'")
+            .fetch_all(&pool)
+            .await
+            .unwrap();
+        assert!(tables.is_empty(), "{tables:?}");
+        pool.close().await;
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[tokio::test]
+    async fn check_database_given_unknown_migration_then_fail() {
+        // Given
+        let (config, dir) = temp_config();
+        let pool = setup_database(&config).await.unwrap();
+        sqlx::query(
+            "INSERT INTO _sqlx_migrations (version, description, success, checksum, execution_time) \
+             VALUES (99990101000000, 'from the future', TRUE, X'00', 0)",
+        )
+        .execute(&pool)
+        .await
+        .unwrap();
+        pool.close().await;
+
+        // When
+        let checks = check_database(&config).await;
+
+        // Then
+        let migrations = find(&checks, "migrations");
+        assert_eq!(migrations.status, CheckStatus::Fail);
+        assert!(migrations.message.contains("99990101000000"));
+        assert_eq!(find(&checks, "integrity").status, CheckStatus::Pass);
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn check_config_given_invalid_values_then_fail_per_field_and_warn_unset_admin_token() {
+        // Given
+        let mut config = Config::parse_from([env!("CARGO_PKG_NAME"), "--server-unix-socket-mode", "999"]);
+        config.server_tls_cert = Some("cert.pem".into());
+
+        // When
+        let checks = check_config(&config);
+
+        // Then
+        let failed: Vec<&str> = checks
+            .iter()
+            .filter(|check| check.status == CheckStatus::Fail)
+            .map(|check| check.message.as_str())
+            .collect();
+        assert_eq!(failed.len(), 2, "{failed:?}");
+        assert!(failed.iter().any(|message| message.starts_with("server_unix_socket_mode: ")));
+        assert!(failed.contains(&"server_tls_key: must be set along with server_tls_cert"));
+        assert!(
+            checks
+                .iter()
+                .any(|check| check.status == CheckStatus::Warn && check.message.starts_with("admin_token is unset"))
+        );
+    }
+
+    #[tokio::test]
+    async fn check_port_given_bound_port_then_fail() {
+        // Given
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let port = listener.local_addr().unwrap().port();
+
+        // When
+        let check = check_port("port", IpAddr::V4(Ipv4Addr::LOCALHOST), port).await;
+
+        // Then
+        assert_eq!(check.status, CheckStatus::Fail);
+        assert!(check.message.contains("in use"));
+    }
+
+    #[test]
+    fn check_tls_given_unreadable_key_then_fail_naming_it() {
+        // Given
+        let (mut config, dir) = temp_config();
+        let (cert, key) = (dir.join("cert.pem"), dir.join("key.pem"));
+        std::fs::write(&cert, "-----BEGIN CERTIFICATE-----\n").unwrap();
+        config.server_tls_cert = Some(cert);
+        config.server_tls_key = Some(key.clone());
+
+        // When
+        let check = check_tls(&config);
+
+        // Then
+        assert_eq!(check.status, CheckStatus::Fail);
+        assert!(check.message.starts_with(&format!("can't read {}", key.display())));
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+}
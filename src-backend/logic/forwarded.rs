@@ -0,0 +1,163 @@
+use axum::http::{HeaderMap, HeaderName, header::FORWARDED};
+use ipnet::IpNet;
+use std::net::IpAddr;
+
+const X_FORWARDED_FOR: HeaderName = HeaderName::from_static("x-forwarded-for");
+const X_FORWARDED_PROTO: HeaderName = HeaderName::from_static("x-forwarded-proto");
+
+/// Client of a request as resolved through trusted proxies
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ClientInfo {
+    pub ip: Option<IpAddr>,
+    /// `http` or `https` as seen by the client, `None` when not forwarded
+    pub scheme: Option<String>,
+}
+
+/// Hop of a proxy chain, from a `Forwarded` element or the `X-Forwarded-*` headers
+#[derive(Debug, Default)]
+struct Hop {
+    ip: Option<IpAddr>,
+    scheme: Option<String>,
+}
+
+/// Proxies whose forwarding headers are believed, from `server_trusted_proxies`
+#[derive(Debug, Clone, Default)]
+pub struct TrustedProxies(Vec<IpNet>);
+
+impl TrustedProxies {
+    pub fn new(networks: Vec<IpNet>) -> Self {
+        Self(networks)
+    }
+
+    /// Peers without an address (unix sockets) are local reverse proxies, trusted once any proxy is
+    fn is_trusted(&self, peer: Option<IpAddr>) -> bool {
+        match peer {
+            Some(ip) => self.0.iter().any(|network| network.contains(&ip)),
+            None => !self.0.is_empty(),
+        }
+    }
+
+    /// Walks the forwarding chain from the connected peer backwards, stopping at the first untrusted hop
+    pub fn resolve(&self, peer: Option<IpAddr>, headers: &HeaderMap) -> ClientInfo {
+        let mut client = ClientInfo { ip: peer, scheme: None };
+        if !self.is_trusted(peer) {
+            return client;
+        }
+
+        for hop in forwarded_hops(headers).into_iter().rev() {
+            let Some(ip) = hop.ip else { break };
+            client = ClientInfo {
+                ip: Some(ip),
+                scheme: hop.scheme.or(client.scheme),
+            };
+            if !self.is_trusted(Some(ip)) {
+                break;
+            }
+        }
+        client
+    }
+}
+
+/// Hops in client-to-proxy order, preferring the standard `Forwarded` header over `X-Forwarded-*`
+fn forwarded_hops(headers: &HeaderMap) -> Vec<Hop> {
+    let values = |name: &HeaderName| -> Vec<String> {
+        headers
+            .get_all(name)
+            .iter()
+            .filter_map(|value| value.to_str().ok())
+            .flat_map(|value| value.split(','))
+            .map(|item| item.trim().to_string())
+            .collect()
+    };
+
+    let forwarded = values(&FORWARDED);
+    if !forwarded.is_empty() {
+        return forwarded.iter().map(|element| parse_forwarded_element(element)).collect();
+    }
+
+    let mut hops: Vec<Hop> = values(&X_FORWARDED_FOR)
+        .iter()
+        .map(|ip| Hop {
+            ip: parse_node(ip),
+            scheme: None,
+        })
+        .collect();
+    // The proxy sets the protocol its client connected with, belonging to the last hop
+    if let (Some(last), Some(proto)) = (hops.last_mut(), values(&X_FORWARDED_PROTO).pop()) {
+        last.scheme = Some(proto.to_ascii_lowercase());
+    }
+    hops
+}
+
+/// Parses `for=...;proto=...` of a `Forwarded` element (RFC 7239)
+fn parse_forwarded_element(element: &str) -> Hop {
+    let mut hop = Hop::default();
+    for pair in element.split(';') {
+        let Some((key, value)) = pair.split_once('=') else { continue };
+        let value = value.trim().trim_matches('"');
+        match key.trim().to_ascii_lowercase().as_str() {
+            "for" => hop.ip = parse_node(value),
+            "proto" => hop.scheme = Some(value.to_ascii_lowercase()),
+            _ => {}
+        }
+    }
+    hop
+}
+
+/// Parses a node as `ip`, `ip:port`, `[ipv6]` or `[ipv6]:port`, obfuscated identifiers yielding `None`
+fn parse_node(node: &str) -> Option<IpAddr> {
+    if let Ok(ip) = node.parse() {
+        return Some(ip);
+    }
+    if let Some(rest) = node.strip_prefix('[') {
+        return rest.split(']').next()?.parse().ok();
+    }
+    node.rsplit_once(':')?.0.parse().ok()
+}
+
+#[cfg(test)]
+mod test {
+    use axum::http::{HeaderMap, HeaderValue};
+    use std::net::IpAddr;
+
+    use super::{ClientInfo, TrustedProxies};
+
+    #[test]
+    fn resolve_given_trusted_proxy_chain_then_first_untrusted_hop_is_client() {
+        // Given
+        let proxies = TrustedProxies::new(vec!["10.0.0.0/8".parse().unwrap()]);
+        let mut headers = HeaderMap::new();
+        headers.insert("x-forwarded-for", HeaderValue::from_static("6.6.6.6, 203.0.113.7, 10.0.0.2"));
+        headers.insert("x-forwarded-proto", HeaderValue::from_static("https"));
+        let mut forwarded = HeaderMap::new();
+        forwarded.insert(
+            "forwarded",
+            HeaderValue::from_static(r#"for="[2001:db8::1]:4711";proto=https, for=10.0.0.2"#),
+        );
+        let proxy: IpAddr = "10.0.0.1".parse().unwrap();
+        let stranger: IpAddr = "198.51.100.1".parse().unwrap();
+
+        // When
+        let via_proxy = proxies.resolve(Some(proxy), &headers);
+        let via_forwarded = proxies.resolve(Some(proxy), &forwarded);
+        let spoofed = proxies.resolve(Some(stranger), &headers);
+
+        // Then
+        assert_eq!(
+            via_proxy,
+            ClientInfo {
+                ip: Some("203.0.113.7".parse().unwrap()),
+                scheme: Some("https".into())
+            }
+        );
+        assert_eq!(via_forwarded.ip, Some("2001:db8::1".parse().unwrap()));
+        assert_eq!(via_forwarded.scheme, Some("https".into()));
+        assert_eq!(
+            spoofed,
+            ClientInfo {
+                ip: Some(stranger),
+                scheme: None
+            }
+        );
+    }
+}
@@ -0,0 +1,174 @@
+use crate::{Result, model::Config};
+use axum::Router;
+use color_eyre::eyre::eyre;
+use listenfd::ListenFd;
+use std::net::SocketAddr;
+use tokio::{net::TcpListener, task::JoinSet};
+use tracing::info;
+
+/// Socket the web server accepts connections on
+#[derive(Debug)]
+pub enum Listener {
+    Tcp(TcpListener),
+    #[cfg(unix)]
+    Unix {
+        listener: tokio::net::UnixListener,
+        /// `None` for inherited sockets, whose file is owned by systemd
+        socket_file: Option<SocketFile>,
+    },
+}
+
+/// Unix domain socket file, removed once its listener is dropped
+#[cfg(unix)]
+#[derive(Debug)]
+pub struct SocketFile(std::path::PathBuf);
+
+#[cfg(unix)]
+impl Drop for SocketFile {
+    fn drop(&mut self) {
+        let _ = std::fs::remove_file(&self.0);
+    }
+}
+
+impl Listener {
+    /// Serves the router on the listener until it fails
+    async fn serve(self, router: Router) -> Result {
+        match self {
+            Listener::Tcp(listener) => axum::serve(listener, router.into_make_service_with_connect_info::<SocketAddr>()).await?,
+            #[cfg(unix)]
+            Listener::Unix { listener, socket_file } => {
+                let _socket_file = socket_file;
+                axum::serve(listener, router.into_make_service()).await?
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Binds every configured listener: sockets inherited through systemd's `LISTEN_FDS`,
+/// the Unix domain socket at `server_unix_socket` and the TCP socket on `server_host:server_port`
+pub async fn bind_listeners(config: &Config) -> Result<Vec<Listener>> {
+    let mut listeners = inherited_listeners()?;
+
+    #[cfg(unix)]
+    if let Some(path) = &config.server_unix_socket {
+        use std::os::unix::fs::{FileTypeExt, PermissionsExt};
+
+        let mode = u32::from_str_radix(&config.server_unix_socket_mode, 8)
+            .map_err(|_| eyre!("Invalid octal socket mode '{}'", config.server_unix_socket_mode))?;
+        match std::fs::symlink_metadata(path) {
+            Ok(meta) if meta.file_type().is_socket() => std::fs::remove_file(path)?, // Stale socket of a previous run
+            Ok(_) => return Err(eyre!("{} exists and is not a socket, refusing to replace it", path.display())),
+            Err(err) if err.kind() == std::io::ErrorKind::NotFound => {}
+            Err(err) => return Err(err.into()),
+        }
+        let listener = tokio::net::UnixListener::bind(path)?;
+        std::fs::set_permissions(path, std::fs::Permissions::from_mode(mode))?;
+        info!("Listening on unix socket {} (mode {mode:o})", path.display());
+        listeners.push(Listener::Unix {
+            listener,
+            socket_file: Some(SocketFile(path.clone())),
+        });
+    }
+
+    if config.server_tcp {
+        let addr = SocketAddr::from((config.server_host, config.server_port));
+        info!("Listening on {addr}");
+        listeners.push(Listener::Tcp(TcpListener::bind(addr).await?));
+    }
+
+    if listeners.is_empty() {
+        return Err(eyre!("No listener configured, enable server_tcp or set server_unix_socket"));
+    }
+    Ok(listeners)
+}
+
+/// Sockets passed by systemd socket activation, empty when not socket activated
+fn inherited_listeners() -> Result<Vec<Listener>> {
+    let mut fds = ListenFd::from_env();
+    let mut listeners = Vec::with_capacity(fds.len());
+    for index in 0..fds.len() {
+        // Errors when the socket is not TCP, leaving it to be taken as a Unix socket
+        if let Ok(Some(listener)) = fds.take_tcp_listener(index) {
+            listener.set_nonblocking(true)?;
+            info!("Listening on inherited socket {}", listener.local_addr()?);
+            listeners.push(Listener::Tcp(TcpListener::from_std(listener)?));
+            continue;
+        }
+
+        #[cfg(unix)]
+        if let Some(listener) = fds.take_unix_listener(index)? {
+            listener.set_nonblocking(true)?;
+            info!("Listening on inherited unix socket #{index}");
+            listeners.push(Listener::Unix {
+                listener: tokio::net::UnixListener::from_std(listener)?,
+                socket_file: None,
+            });
+            continue;
+        }
+
+        return Err(eyre!("Inherited file descriptor #{index} is not a stream socket"));
+    }
+    Ok(listeners)
+}
+
+/// Serves the router on every listener concurrently, returning once all stopped or on the first failure
+pub async fn serve_all(listeners: Vec<Listener>, router: Router) -> Result {
+    let mut servers = JoinSet::new();
+    for listener in listeners {
+        servers.spawn(listener.serve(router.clone()));
+    }
+    while let Some(result) = servers.join_next().await {
+        result??;
+    }
+    Ok(())
+}
+
+#[cfg(all(test, unix))]
+mod test {
+    use clap::Parser;
+    use uuid::Uuid;
+
+    use super::bind_listeners;
+    use crate::model::Config;
+
+    #[tokio::test]
+    async fn bind_listeners_given_stale_socket_then_replaced_and_removed_on_drop() {
+        // Given
+        let dir = std::env::temp_dir().join(format!("breezi-{}", Uuid::new_v4()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let socket = dir.join("breezi.sock");
+        drop(std::os::unix::net::UnixListener::bind(&socket).unwrap());
+        let mut config = Config::parse_from([env!("CARGO_PKG_NAME"), "--server-unix-socket", socket.to_str().unwrap()]);
+        config.server_tcp = false;
+
+        // When
+        let listeners = bind_listeners(&config).await.unwrap();
+
+        // Then
+        assert_eq!(listeners.len(), 1);
+        assert!(tokio::net::UnixStream::connect(&socket).await.is_ok());
+        drop(listeners);
+        assert!(!socket.exists());
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[tokio::test]
+    async fn bind_listeners_given_regular_file_at_socket_path_then_error_and_file_kept() {
+        // Given
+        let dir = std::env::temp_dir().join(format!("breezi-{}", Uuid::new_v4()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("breezi.sock");
+        std::fs::write(&path, "not a socket").unwrap();
+        let mut config = Config::parse_from([env!("CARGO_PKG_NAME"), "--server-unix-socket", path.to_str().unwrap()]);
+        config.server_tcp = false;
+
+        // When
+        let result = bind_listeners(&config).await;
+
+        // Then
+        assert!(result.unwrap_err().to_string().contains("not a socket"));
+        assert_eq!(std::fs::read_to_string(&path).unwrap(), "not a socket");
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+}
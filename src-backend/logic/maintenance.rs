@@ -0,0 +1,236 @@
+use crate::model::{Config, ErrorReason, ErrorResponse, MaintenanceMode};
+use axum::{
+    body::Body,
+    http::{
+        Method, Request, Response,
+        header::{AUTHORIZATION, CONTENT_TYPE, HOST},
+    },
+};
+use color_eyre::eyre::{bail, eyre};
+use http_body_util::BodyExt;
+use hyper::body::Incoming;
+use hyper_util::rt::TokioIo;
+use serde_json::{Value, json};
+use std::{
+    fmt::{self, Display},
+    net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr},
+    sync::atomic::{AtomicU8, Ordering},
+};
+use tokio::{
+    io::{AsyncRead, AsyncWrite},
+    net::TcpStream,
+};
+use tracing::info;
+
+/// Runtime maintenance mode, shared by the router and qubit handlers
+#[derive(Debug)]
+pub struct Maintenance(AtomicU8);
+
+impl Maintenance {
+    const MODES: [MaintenanceMode; 3] = [MaintenanceMode::Off, MaintenanceMode::ReadOnly, MaintenanceMode::Full];
+
+    pub fn new(mode: MaintenanceMode) -> Self {
+        Self(AtomicU8::new(mode as u8))
+    }
+
+    pub fn mode(&self) -> MaintenanceMode {
+        Self::MODES[usize::from(self.0.load(Ordering::Relaxed))]
+    }
+
+    pub fn set(&self, mode: MaintenanceMode) {
+        self.0.store(mode as u8, Ordering::Relaxed);
+    }
+
+    /// Rejects a handler call the current mode forbids, admin handlers staying reachable to lift maintenance
+    pub fn check(&self, handler: &str, is_mutation: bool) -> Result<(), ErrorResponse> {
+        let message = match self.mode() {
+            _ if handler.starts_with("admin.") => return Ok(()),
+            MaintenanceMode::Off => return Ok(()),
+            MaintenanceMode::ReadOnly if !is_mutation => return Ok(()),
+            MaintenanceMode::ReadOnly => "Down for maintenance, changes are disabled for now",
+            MaintenanceMode::Full => "Down for maintenance, please try again later",
+        };
+        Err(ErrorResponse::new(ErrorReason::ServiceUnavailable, message.into()))
+    }
+}
+
+/// Where a server running with a given config can be reached from the same host
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ServerAddress {
+    Tcp(SocketAddr),
+    #[cfg(unix)]
+    Unix(std::path::PathBuf),
+}
+
+impl ServerAddress {
+    /// Prefers TCP, reaching wildcard hosts (`0.0.0.0`, `::`) over loopback, else the unix socket
+    pub fn of(config: &Config) -> crate::Result<Self> {
+        if config.server_tcp {
+            let host = match config.server_host {
+                IpAddr::V4(host) if host.is_unspecified() => IpAddr::V4(Ipv4Addr::LOCALHOST),
+                IpAddr::V6(host) if host.is_unspecified() => IpAddr::V6(Ipv6Addr::LOCALHOST),
+                host => host,
+            };
+            return Ok(Self::Tcp(SocketAddr::new(host, config.server_port)));
+        }
+        #[cfg(unix)]
+        if let Some(path) = &config.server_unix_socket {
+            return Ok(Self::Unix(path.clone()));
+        }
+        bail!("server_tcp is disabled and no server_unix_socket is set, the server can't be reached")
+    }
+
+    /// Value of the `Host` header, unix sockets having no host of their own
+    fn host(&self) -> String {
+        match self {
+            Self::Tcp(addr) => addr.to_string(),
+            #[cfg(unix)]
+            Self::Unix(_) => "localhost".into(),
+        }
+    }
+
+    async fn send(&self, request: Request<Body>) -> crate::Result<Response<Incoming>> {
+        match self {
+            Self::Tcp(addr) => send_over(TcpStream::connect(addr).await?, request).await,
+            #[cfg(unix)]
+            Self::Unix(path) => send_over(tokio::net::UnixStream::connect(path).await?, request).await,
+        }
+    }
+}
+
+impl Display for ServerAddress {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Tcp(addr) => write!(f, "http://{addr}"),
+            #[cfg(unix)]
+            Self::Unix(path) => write!(f, "unix:{}", path.display()),
+        }
+    }
+}
+
+/// Sends a single HTTP/1 request over a fresh connection
+async fn send_over<S>(stream: S, request: Request<Body>) -> crate::Result<Response<Incoming>>
+where
+    S: AsyncRead + AsyncWrite + Unpin + Send + 'static,
+{
+    let (mut sender, connection) = hyper::client::conn::http1::handshake(TokioIo::new(stream)).await?;
+    tokio::spawn(connection);
+    Ok(sender.send_request(request).await?)
+}
+
+/// Sets the maintenance mode of the server running with `config` through its `admin.set_maintenance` RPC
+pub async fn request_maintenance(config: &Config, mode: MaintenanceMode) -> crate::Result {
+    let Some(token) = &config.admin_token else {
+        bail!("admin_token must be set to change the maintenance mode");
+    };
+    let server = ServerAddress::of(config)?;
+    let call = json!({ "jsonrpc": "2.0", "id": 1, "method": "admin.set_maintenance", "params": [mode] });
+    let request = Request::builder()
+        .method(Method::POST)
+        .uri(format!("{}rpc", config.base_path()))
+        .header(HOST, server.host())
+        .header(CONTENT_TYPE, "application/json")
+        .header(AUTHORIZATION, format!("Bearer {}", token.expose()))
+        .body(Body::from(call.to_string()))?;
+
+    let response = server.send(request).await?;
+    let body: Value = serde_json::from_slice(&response.into_body().collect().await?.to_bytes())?;
+    match body.pointer("/result/Err").or_else(|| body.get("error")) {
+        Some(err) => Err(eyre!("Server at {server} refused maintenance mode: {err}")),
+        None => {
+            info!("Maintenance mode set to {mode:?} on {server}");
+            Ok(())
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use axum::{Json, Router, http::HeaderMap, routing::post};
+    use clap::Parser;
+    use serde_json::{Value, json};
+    use uuid::Uuid;
+
+    use super::{Maintenance, ServerAddress, request_maintenance};
+    use crate::model::{Config, ErrorReason, MaintenanceMode};
+
+    #[test]
+    fn check_given_read_only_then_only_non_admin_mutations_rejected() {
+        // Given
+        let maintenance = Maintenance::new(MaintenanceMode::ReadOnly);
+
+        // When
+        let mutation = maintenance.check("register", true);
+        let query = maintenance.check("profile", false);
+        let admin = maintenance.check("admin.set_maintenance", true);
+
+        // Then
+        assert_eq!(mutation.map_err(|err| err.reason), Err(ErrorReason::ServiceUnavailable));
+        assert!(query.is_ok());
+        assert!(admin.is_ok());
+    }
+
+    #[test]
+    fn check_given_switched_to_full_then_queries_rejected() {
+        // Given
+        let maintenance = Maintenance::new(MaintenanceMode::ReadOnly);
+
+        // When
+        maintenance.set(MaintenanceMode::Full);
+        let query = maintenance.check("profile", false);
+
+        // Then
+        assert_eq!(query.map_err(|err| err.reason), Err(ErrorReason::ServiceUnavailable));
+    }
+
+    #[test]
+    fn server_address_given_wildcard_hosts_then_loopback() {
+        // Given
+        let config = |host: &str| Config::parse_from([env!("CARGO_PKG_NAME"), "--server-host", host, "--server-port", "8080"]);
+
+        // When
+        let v4 = ServerAddress::of(&config("0.0.0.0")).unwrap();
+        let v6 = ServerAddress::of(&config("::")).unwrap();
+        let mut no_listener = config("::");
+        no_listener.server_tcp = false;
+
+        // Then
+        assert_eq!(v4.to_string(), "http://127.0.0.1:8080");
+        assert_eq!(v6.to_string(), "http://[::1]:8080");
+        assert!(ServerAddress::of(&no_listener).is_err());
+    }
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn request_maintenance_given_unix_socket_only_then_sent_over_socket() {
+        // Given
+        let dir = std::env::temp_dir().join(format!("breezi-{}", Uuid::new_v4()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let socket = dir.join("breezi.sock");
+        let router = Router::new().route(
+            "/rpc",
+            post(|headers: HeaderMap, Json(call): Json<Value>| async move {
+                assert_eq!(headers["authorization"], "Bearer admin-secret");
+                assert_eq!(call["params"], json!(["read_only"]));
+                Json(json!({ "jsonrpc": "2.0", "id": 1, "result": { "Ok": null } }))
+            }),
+        );
+        let listener = tokio::net::UnixListener::bind(&socket).unwrap();
+        tokio::spawn(async move { axum::serve(listener, router).await });
+        let mut config = Config::parse_from([
+            env!("CARGO_PKG_NAME"),
+            "--admin-token",
+            "admin-secret",
+            "--server-unix-socket",
+            socket.to_str().unwrap(),
+        ]);
+        config.server_tcp = false;
+
+        // When
+        let result = request_maintenance(&config, MaintenanceMode::ReadOnly).await;
+
+        // Then
+        assert!(result.is_ok(), "{result:?}");
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+}
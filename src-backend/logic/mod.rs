@@ -1,7 +1,33 @@
 mod bindings;
+mod commands;
+mod config_file;
+mod doctor;
+mod forwarded;
+mod listeners;
+mod maintenance;
+mod prometheus;
+mod rate_limit;
+mod reload;
+mod request_scope;
+mod seed;
+mod setup;
 mod sqlite;
+mod telemetry;
 mod validation;
 
 pub use bindings::*;
+pub use commands::*;
+pub use config_file::*;
+pub use doctor::*;
+pub use forwarded::*;
+pub use listeners::*;
+pub use maintenance::*;
+pub use prometheus::*;
+pub use rate_limit::*;
+pub use reload::*;
+pub use request_scope::*;
+pub use seed::*;
+pub use setup::*;
 pub use sqlite::*;
+pub use telemetry::*;
 pub use validation::*;
@@ -0,0 +1,67 @@
+use crate::model::Config;
+use metrics::{counter, gauge, histogram};
+use metrics_exporter_prometheus::{Matcher, PrometheusBuilder, PrometheusHandle};
+use sqlx::{Sqlite, SqlitePool, pool::PoolConnection};
+use std::{sync::OnceLock, time::Instant};
+use tracing::info;
+
+/// Latency buckets (seconds) applied to every `*_duration_seconds` histogram
+const DURATION_BUCKETS: &[f64] = &[0.001, 0.005, 0.01, 0.025, 0.05, 0.1, 0.25, 0.5, 1.0, 2.5, 5.0, 10.0];
+
+static HANDLE: OnceLock<PrometheusHandle> = OnceLock::new();
+
+/// Installs the global Prometheus recorder, does nothing when metrics are disabled
+pub fn install_metrics(config: &Config) -> crate::Result {
+    if !config.metrics_enabled {
+        return Ok(());
+    }
+
+    info!("Installing Prometheus metrics recorder");
+    let handle = PrometheusBuilder::new()
+        .set_buckets_for_metric(Matcher::Suffix("duration_seconds".into()), DURATION_BUCKETS)?
+        .install_recorder()?;
+    let _ = HANDLE.set(handle);
+
+    let profile = if cfg!(debug_assertions) { "debug" } else { "release" };
+    gauge!("breezi_build_info", "version" => env!("CARGO_PKG_VERSION"), "profile" => profile).set(1);
+
+    Ok(())
+}
+
+/// Renders the current metrics in the Prometheus text format, refreshing pool gauges first
+pub fn render_metrics(pool: &SqlitePool) -> Option<String> {
+    let handle = HANDLE.get()?;
+
+    gauge!("db_pool_size").set(pool.size());
+    gauge!("db_pool_idle").set(pool.num_idle() as f64);
+    gauge!("db_pool_max").set(pool.options().get_max_connections());
+
+    handle.run_upkeep();
+    Some(handle.render())
+}
+
+/// Records an HTTP request outcome for the given route
+pub fn record_http(method: &str, route: &str, status: u16, started: Instant) {
+    let labels = [
+        ("method", method.to_string()),
+        ("route", route.to_string()),
+        ("status", status.to_string()),
+    ];
+    counter!("http_requests_total", &labels).increment(1);
+    histogram!("http_request_duration_seconds", &labels).record(started.elapsed().as_secs_f64());
+}
+
+/// Records a qubit handler call, `outcome` being `Ok` or the `ErrorReason` variant name
+pub fn record_handler(handler: &'static str, outcome: &'static str, started: Instant) {
+    counter!("rpc_handler_calls_total", "handler" => handler, "outcome" => outcome).increment(1);
+    histogram!("rpc_handler_duration_seconds", "handler" => handler, "outcome" => outcome)
+        .record(started.elapsed().as_secs_f64());
+}
+
+/// Acquires a pooled connection, recording how long the caller waited for it
+pub async fn acquire(pool: &SqlitePool) -> Result<PoolConnection<Sqlite>, sqlx::Error> {
+    let started = Instant::now();
+    let conn = pool.acquire().await;
+    histogram!("db_pool_acquire_duration_seconds").record(started.elapsed().as_secs_f64());
+    conn
+}
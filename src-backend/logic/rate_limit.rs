@@ -0,0 +1,256 @@
+use crate::{
+    logic::{Caller, RequestScope},
+    model::{Config, ErrorReason, ErrorResponse, RateLimitKey},
+};
+use std::{
+    collections::HashMap,
+    sync::Mutex,
+    time::{Duration, Instant},
+};
+
+/// Interval between prunings of idle buckets, a bucket refilling completely within a minute
+const PRUNE_INTERVAL: Duration = Duration::from_secs(60);
+
+/// Remaining quota of a client after an allowed call
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RateLimitStatus {
+    pub limit: u32,
+    pub remaining: u32,
+    /// Seconds until the bucket is full again
+    pub reset_secs: u32,
+}
+
+/// Quota exhausted, the client may retry after `retry_after_secs`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RateLimited {
+    pub limit: u32,
+    pub retry_after_secs: u32,
+}
+
+impl From<RateLimited> for ErrorResponse {
+    fn from(value: RateLimited) -> Self {
+        ErrorResponse::new(
+            ErrorReason::TooManyRequests {
+                retry_after_secs: value.retry_after_secs,
+            },
+            format!("Rate limit of {} calls per minute exceeded", value.limit),
+        )
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+struct Bucket {
+    tokens: f64,
+    updated: Instant,
+}
+
+#[derive(Debug)]
+struct Buckets {
+    by_key: HashMap<String, Bucket>,
+    pruned: Instant,
+}
+
+/// Token bucket limiter per client key, holding up to `per_minute` tokens refilled evenly over a minute
+#[derive(Debug)]
+pub struct RateLimiter {
+    per_minute: u32,
+    buckets: Mutex<Buckets>,
+}
+
+impl RateLimiter {
+    pub fn per_minute(per_minute: u32) -> Self {
+        Self {
+            per_minute,
+            buckets: Mutex::new(Buckets {
+                by_key: HashMap::new(),
+                pruned: Instant::now(),
+            }),
+        }
+    }
+
+    /// Takes a token from the client's bucket
+    pub fn check(&self, key: &str) -> Result<RateLimitStatus, RateLimited> {
+        self.check_at(key, Instant::now())
+    }
+
+    fn check_at(&self, key: &str, now: Instant) -> Result<RateLimitStatus, RateLimited> {
+        let capacity = f64::from(self.per_minute);
+        let refilled = |bucket: &Bucket| {
+            let elapsed = now.saturating_duration_since(bucket.updated).as_secs_f64();
+            (bucket.tokens + elapsed * capacity / 60.0).min(capacity)
+        };
+        let secs_until = |tokens: f64| (tokens * 60.0 / capacity).ceil() as u32;
+
+        let mut buckets = self.buckets.lock().unwrap_or_else(|poisoned| poisoned.into_inner());
+        if now.saturating_duration_since(buckets.pruned) >= PRUNE_INTERVAL {
+            buckets.by_key.retain(|_, bucket| refilled(bucket) < capacity);
+            buckets.pruned = now;
+        }
+
+        let bucket = buckets.by_key.entry(key.to_string()).or_insert(Bucket {
+            tokens: capacity,
+            updated: now,
+        });
+        bucket.tokens = refilled(bucket);
+        bucket.updated = now;
+
+        if bucket.tokens < 1.0 {
+            return Err(RateLimited {
+                limit: self.per_minute,
+                retry_after_secs: secs_until(1.0 - bucket.tokens),
+            });
+        }
+
+        bucket.tokens -= 1.0;
+        Ok(RateLimitStatus {
+            limit: self.per_minute,
+            remaining: bucket.tokens.floor() as u32,
+            reset_secs: secs_until(capacity - bucket.tokens),
+        })
+    }
+}
+
+/// Router and per-handler limiters built from the `rate_limit_*` settings
+#[derive(Debug)]
+pub struct RateLimits {
+    key: RateLimitKey,
+    http: Option<RateLimiter>,
+    rpc: Option<RateLimiter>,
+    handlers: HashMap<String, RateLimiter>,
+}
+
+impl RateLimits {
+    pub fn new(config: &Config) -> Self {
+        let limiter = |per_minute: u32| (per_minute > 0).then(|| RateLimiter::per_minute(per_minute));
+        Self {
+            key: config.rate_limit_key,
+            http: limiter(config.rate_limit_http),
+            rpc: limiter(config.rate_limit_rpc),
+            handlers: config
+                .rate_limit_handlers
+                .iter()
+                .filter_map(|quota| Some((quota.handler.clone(), limiter(quota.value)?)))
+                .collect(),
+        }
+    }
+
+    /// Checks the router-wide quota, `None` when unlimited
+    pub fn check_http(&self, scope: &RequestScope) -> Option<Result<RateLimitStatus, RateLimited>> {
+        Some(self.http.as_ref()?.check(&self.client_key(scope)))
+    }
+
+    /// Checks the quota of a qubit handler, its override taking precedence over the shared RPC quota
+    pub fn check_handler(&self, handler: &str, scope: &RequestScope) -> Result<(), RateLimited> {
+        let client = self.client_key(scope);
+        match (self.handlers.get(handler), &self.rpc) {
+            (Some(limiter), _) => limiter.check(&client)?,
+            (None, Some(limiter)) => limiter.check(&format!("{handler}:{client}"))?,
+            (None, None) => return Ok(()),
+        };
+        Ok(())
+    }
+
+    fn client_key(&self, scope: &RequestScope) -> String {
+        let ip = || {
+            scope
+                .client_ip
+                .map(|ip| format!("ip:{ip}"))
+                .unwrap_or_else(|| "ip:unknown".into())
+        };
+        match (self.key, &scope.caller) {
+            (RateLimitKey::User, Caller::User { user_id, .. }) => format!("user:{user_id}"),
+            (RateLimitKey::ApiKey, Caller::User { api_key_id, .. }) => format!("key:{api_key_id}"),
+            (RateLimitKey::User | RateLimitKey::ApiKey, Caller::Admin) => "admin".into(),
+            // Unverified tokens would let a client pick a fresh bucket per request
+            (_, Caller::Anonymous) | (RateLimitKey::Ip, _) => ip(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use clap::Parser;
+    use std::time::{Duration, Instant};
+
+    use super::{PRUNE_INTERVAL, RateLimitStatus, RateLimited, RateLimiter, RateLimits};
+    use crate::{
+        logic::{Caller, RequestScope},
+        model::Config,
+    };
+
+    #[test]
+    fn check_given_quota_exhausted_then_limited_until_refilled() {
+        // Given
+        let limiter = RateLimiter::per_minute(2);
+        let now = Instant::now();
+
+        // When
+        let first = limiter.check_at("client", now);
+        let second = limiter.check_at("client", now);
+        let third = limiter.check_at("client", now);
+        let other_client = limiter.check_at("other", now);
+        let after_refill = limiter.check_at("client", now + Duration::from_secs(30));
+
+        // Then
+        assert_eq!(
+            first,
+            Ok(RateLimitStatus {
+                limit: 2,
+                remaining: 1,
+                reset_secs: 30
+            })
+        );
+        assert_eq!(second.map(|status| status.remaining), Ok(0));
+        assert_eq!(
+            third,
+            Err(RateLimited {
+                limit: 2,
+                retry_after_secs: 30
+            })
+        );
+        assert!(other_client.is_ok());
+        assert_eq!(after_refill.map(|status| status.remaining), Ok(0));
+    }
+
+    #[test]
+    fn check_given_prune_interval_elapsed_then_idle_buckets_dropped() {
+        // Given
+        let limiter = RateLimiter::per_minute(2);
+        let now = Instant::now();
+        limiter.check_at("idle", now).unwrap();
+
+        // When
+        limiter.check_at("active", now + PRUNE_INTERVAL).unwrap();
+
+        // Then
+        let buckets = limiter.buckets.lock().unwrap();
+        assert!(!buckets.by_key.contains_key("idle"));
+        assert!(buckets.by_key.contains_key("active"));
+    }
+
+    #[test]
+    fn client_key_given_unverified_caller_then_keyed_by_ip() {
+        // Given
+        let limits = |key: &str| RateLimits::new(&Config::parse_from([env!("CARGO_PKG_NAME"), "--rate-limit-key", key]));
+        let scope = |caller: Caller| RequestScope {
+            client_ip: Some([203, 0, 113, 7].into()),
+            caller,
+            ..RequestScope::default()
+        };
+        let user = Caller::User {
+            user_id: "u1".into(),
+            api_key_id: "k1".into(),
+        };
+
+        // When
+        let keys = [
+            limits("user").client_key(&scope(user.clone())),
+            limits("api-key").client_key(&scope(user)),
+            limits("api-key").client_key(&scope(Caller::Anonymous)),
+            limits("user").client_key(&scope(Caller::Admin)),
+        ];
+
+        // Then
+        assert_eq!(keys, ["user:u1", "key:k1", "ip:203.0.113.7", "admin"]);
+    }
+}
@@ -0,0 +1,188 @@
+use crate::{
+    logic::{RateLimits, set_log_filter},
+    model::{Cli, Config, profile_path},
+};
+use arc_swap::ArcSwap;
+use serde_json::Value;
+use std::{
+    path::PathBuf,
+    sync::Arc,
+    time::{Duration, SystemTime},
+};
+use tokio::task::JoinHandle;
+use tracing::{error, info, warn};
+
+/// Interval the config files are polled for changes at
+const POLL_INTERVAL: Duration = Duration::from_secs(2);
+
+/// Settings applied live on reload, the rest of [`Config`] needing a restart
+const RELOADABLE: [&str; 7] = [
+    "log_filter",
+    "server_cors",
+    "rate_limit_key",
+    "rate_limit_http",
+    "rate_limit_rpc",
+    "rate_limit_handlers",
+    "request_timeout_handlers",
+];
+
+/// Current config and what is derived from it, replaced as a whole on reload
+#[derive(Debug)]
+pub struct Live {
+    pub config: Config,
+    pub rate_limits: Arc<RateLimits>,
+}
+
+/// Shared, swappable handle on the [`Live`] config
+#[derive(Debug, Clone)]
+pub struct LiveConfig(Arc<ArcSwap<Live>>);
+
+impl LiveConfig {
+    pub fn new(config: Config) -> Self {
+        let rate_limits = Arc::new(RateLimits::new(&config));
+        Self(Arc::new(ArcSwap::from_pointee(Live { config, rate_limits })))
+    }
+
+    pub fn load(&self) -> Arc<Live> {
+        self.0.load_full()
+    }
+
+    /// Applies the reloadable settings of `next`, returning the changed fields applied and ignored
+    pub fn apply(&self, next: &Config) -> crate::Result<(Vec<String>, Vec<String>)> {
+        let current = self.load();
+        let (applied, ignored): (Vec<String>, Vec<String>) = changed_fields(&current.config, next)?
+            .into_iter()
+            .partition(|field| RELOADABLE.contains(&field.as_str()));
+        if applied.is_empty() {
+            return Ok((applied, ignored));
+        }
+
+        let mut config = current.config.clone();
+        config.log_filter = next.log_filter.clone();
+        config.server_cors = next.server_cors;
+        config.rate_limit_key = next.rate_limit_key;
+        config.rate_limit_http = next.rate_limit_http;
+        config.rate_limit_rpc = next.rate_limit_rpc;
+        config.rate_limit_handlers = next.rate_limit_handlers.clone();
+        config.request_timeout_handlers = next.request_timeout_handlers.clone();
+
+        if applied.iter().any(|field| field == "log_filter") {
+            set_log_filter(&config.log_filter)?;
+        }
+        let rate_limits = match applied.iter().any(|field| field.starts_with("rate_limit_")) {
+            true => Arc::new(RateLimits::new(&config)), // Resets the clients' quotas
+            false => current.rate_limits.clone(),
+        };
+        self.0.store(Arc::new(Live { config, rate_limits }));
+        Ok((applied, ignored))
+    }
+}
+
+/// Top level fields whose value differs between two configs
+fn changed_fields(current: &Config, next: &Config) -> crate::Result<Vec<String>> {
+    let (Value::Object(current_values), Value::Object(next_values)) =
+        (serde_json::to_value(current)?, serde_json::to_value(next)?)
+    else {
+        return Ok(Vec::new());
+    };
+    let mut changed: Vec<String> = next_values
+        .into_iter()
+        .filter(|(field, value)| current_values.get(field) != Some(value))
+        .map(|(field, _)| field)
+        .collect();
+    // Secrets all serialize as `<redacted>`, so only their exposed values tell a change
+    if current.admin_token != next.admin_token && !changed.iter().any(|field| field == "admin_token") {
+        changed.push("admin_token".into());
+    }
+    Ok(changed)
+}
+
+/// Polls the config file and its profile overlay, reloading the config when either changes
+pub fn watch_config(live: LiveConfig) -> JoinHandle<()> {
+    let current = live.load();
+    let config = &current.config;
+    let mut paths = vec![config.config_path.clone()];
+    paths.extend(
+        config
+            .config_profile
+            .as_ref()
+            .map(|profile| profile_path(&config.config_path, profile)),
+    );
+
+    tokio::spawn(async move {
+        let mut modified = modified_times(&paths);
+        loop {
+            tokio::time::sleep(POLL_INTERVAL).await;
+            let now = modified_times(&paths);
+            if now == modified {
+                continue;
+            }
+            modified = now;
+
+            info!("Config file changed, reloading");
+            if let Err(err) = reload(&live) {
+                error!("Config reload failed, keeping the current config: {err}");
+            }
+        }
+    })
+}
+
+fn modified_times(paths: &[PathBuf]) -> Vec<Option<SystemTime>> {
+    paths
+        .iter()
+        .map(|path| path.metadata().and_then(|meta| meta.modified()).ok())
+        .collect()
+}
+
+fn reload(live: &LiveConfig) -> crate::Result {
+    let cli = Cli::load()?;
+    cli.check()?;
+    let (applied, ignored) = live.apply(&cli.config)?;
+    if !ignored.is_empty() {
+        warn!("Ignoring changes to {} until restart", ignored.join(", "));
+    }
+    match applied.is_empty() {
+        true => info!("Config reloaded, no live setting changed"),
+        false => info!("Config reloaded, applied {}", applied.join(", ")),
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use clap::Parser;
+
+    use super::LiveConfig;
+    use crate::model::Config;
+
+    #[test]
+    fn apply_given_reloadable_and_restart_changes_then_only_reloadable_applied() {
+        // Given
+        let live = LiveConfig::new(Config::parse_from([env!("CARGO_PKG_NAME")]));
+        let next = Config::parse_from([env!("CARGO_PKG_NAME"), "--rate-limit-http", "10", "--server-port", "9090"]);
+
+        // When
+        let (applied, ignored) = live.apply(&next).unwrap();
+
+        // Then
+        assert_eq!(applied, vec!["rate_limit_http".to_string()]);
+        assert_eq!(ignored, vec!["server_port".to_string()]);
+        assert_eq!(live.load().config.rate_limit_http, 10);
+        assert_eq!(live.load().config.server_port, 8080);
+    }
+
+    #[test]
+    fn apply_given_changed_admin_token_then_reported_ignored() {
+        // Given
+        let live = LiveConfig::new(Config::parse_from([env!("CARGO_PKG_NAME"), "--admin-token", "old-secret"]));
+        let next = Config::parse_from([env!("CARGO_PKG_NAME"), "--admin-token", "new-secret"]);
+
+        // When
+        let (applied, ignored) = live.apply(&next).unwrap();
+
+        // Then
+        assert!(applied.is_empty());
+        assert_eq!(ignored, vec!["admin_token".to_string()]);
+        assert_eq!(live.load().config.admin_token.as_ref().unwrap().expose(), "old-secret");
+    }
+}
@@ -0,0 +1,199 @@
+use crate::{
+    logic::{TrustedProxies, acquire},
+    model::{ApiKey, Config, Secret},
+};
+use axum::{
+    extract::{ConnectInfo, Request, State},
+    http::{self, HeaderMap, HeaderName, header::AUTHORIZATION},
+    middleware::Next,
+    response::Response,
+};
+use sqlx::SqlitePool;
+use std::{
+    net::{IpAddr, SocketAddr},
+    sync::Arc,
+};
+use tracing::warn;
+
+/// Header carrying the request ID, accepted from callers or generated when missing
+pub static REQUEST_ID_HEADER: HeaderName = HeaderName::from_static("x-request-id");
+
+tokio::task_local! {
+    static REQUEST_ID: Option<String>;
+}
+
+/// Per-request details, a request extension read by qubit handlers through [`RequestCtx`](crate::routes::RequestCtx)
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct RequestScope {
+    pub request_id: Option<String>,
+    /// Client address, resolved through trusted proxies
+    pub client_ip: Option<IpAddr>,
+    /// `http` or `https` as forwarded by a trusted proxy
+    pub scheme: Option<String>,
+    pub caller: Caller,
+}
+
+/// Caller identified by the `Authorization: Bearer` token, verified once per request by [`scope_request`]
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub enum Caller {
+    /// No token, or one that is neither the admin token nor an issued API key
+    #[default]
+    Anonymous,
+    /// Token matching `admin_token`
+    Admin,
+    /// Token matching an API key issued to the user
+    User { user_id: String, api_key_id: String },
+}
+
+/// State of [`scope_request`], resolving clients through trusted proxies and verifying bearer tokens
+#[derive(Debug, Clone)]
+pub struct RequestScoping {
+    pub proxies: TrustedProxies,
+    pub admin_token: Option<Secret>,
+    pub pool: SqlitePool,
+}
+
+impl RequestScoping {
+    pub fn new(config: &Config, pool: SqlitePool) -> Self {
+        Self {
+            proxies: TrustedProxies::new(config.server_trusted_proxies.clone()),
+            admin_token: config.admin_token.clone(),
+            pool,
+        }
+    }
+
+    async fn caller(&self, token: Option<&str>) -> Caller {
+        let Some(token) = token else {
+            return Caller::Anonymous;
+        };
+        if self.admin_token.as_ref().is_some_and(|admin| admin.matches(token)) {
+            return Caller::Admin;
+        }
+        match self.api_key(token).await {
+            Ok(Some(key)) => Caller::User {
+                user_id: key.user_id,
+                api_key_id: key.id,
+            },
+            Ok(None) => Caller::Anonymous,
+            Err(err) => {
+                warn!("Failed to verify API key, serving the request anonymously: {err}");
+                Caller::Anonymous
+            }
+        }
+    }
+
+    async fn api_key(&self, token: &str) -> crate::Result<Option<ApiKey>> {
+        let mut conn = acquire(&self.pool).await?;
+        ApiKey::verify(&mut *conn, token).await
+    }
+}
+
+/// Request ID of the request currently being handled, if any
+pub fn current_request_id() -> Option<String> {
+    REQUEST_ID.try_with(Clone::clone).ok().flatten()
+}
+
+/// Middleware inserting the request's [`RequestScope`] extension, its ID also reaching [`current_request_id`]
+pub async fn scope_request(State(scoping): State<Arc<RequestScoping>>, mut req: Request, next: Next) -> Response {
+    let peer = req
+        .extensions()
+        .get::<ConnectInfo<SocketAddr>>()
+        .map(|ConnectInfo(addr)| addr.ip());
+    let client = scoping.proxies.resolve(peer, req.headers());
+    let scope = RequestScope {
+        request_id: request_id(&req),
+        client_ip: client.ip,
+        scheme: client.scheme,
+        caller: scoping.caller(bearer_token(req.headers()).as_deref()).await,
+    };
+    let id = scope.request_id.clone();
+    req.extensions_mut().insert(scope);
+    REQUEST_ID.scope(id, next.run(req)).await
+}
+
+/// Reads the `X-Request-Id` header of a request
+pub fn request_id<B>(req: &http::Request<B>) -> Option<String> {
+    req.headers()
+        .get(&REQUEST_ID_HEADER)
+        .and_then(|value| value.to_str().ok())
+        .map(str::to_string)
+}
+
+/// Reads the `Authorization: Bearer` token of a request
+pub fn bearer_token(headers: &HeaderMap) -> Option<String> {
+    headers
+        .get(AUTHORIZATION)
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.strip_prefix("Bearer "))
+        .map(str::to_string)
+}
+
+#[cfg(test)]
+mod test {
+    use axum::{Json, Router, body::Body, extract::Request, middleware, routing::get};
+    use http_body_util::BodyExt;
+    use std::sync::Arc;
+    use tower::ServiceExt;
+
+    use sqlx::SqlitePool;
+
+    use super::{Caller, RequestScoping, scope_request};
+    use crate::{
+        logic::{TrustedProxies, seeded_database},
+        model::{ApiKey, ErrorResponse, UserListing},
+    };
+
+    #[tokio::test]
+    async fn scope_request_given_request_id_then_error_response_carries_it() {
+        // Given
+        let router = Router::new()
+            .route("/", get(|| async { Json(ErrorResponse::internal()) }))
+            .layer(middleware::from_fn_with_state(
+                Arc::new(RequestScoping {
+                    proxies: TrustedProxies::default(),
+                    admin_token: None,
+                    pool: SqlitePool::connect_lazy("sqlite::memory:").unwrap(),
+                }),
+                scope_request,
+            ));
+        let request = Request::get("/")
+            .header("x-request-id", "req-123")
+            .body(Body::empty())
+            .unwrap();
+
+        // When
+        let response = router.oneshot(request).await.unwrap();
+
+        // Then
+        let body = response.into_body().collect().await.unwrap().to_bytes();
+        let err: ErrorResponse = serde_json::from_slice(&body).unwrap();
+        assert_eq!(err.request_id, Some("req-123".to_string()));
+        assert_eq!(err.message, ErrorResponse::INTERNAL_MESSAGE);
+    }
+
+    #[tokio::test]
+    async fn caller_given_bearer_tokens_then_only_issued_keys_and_admin_token_verified() {
+        // Given
+        let pool = seeded_database("test").await.unwrap();
+        let key = ApiKey::issue(&pool, "test_admin").await.unwrap().unwrap();
+        let scoping = RequestScoping {
+            proxies: TrustedProxies::default(),
+            admin_token: Some("admin-secret".parse().unwrap()),
+            pool: pool.clone(),
+        };
+
+        // When
+        let user = scoping.caller(Some(key.expose())).await;
+        let admin = scoping.caller(Some("admin-secret")).await;
+        let forged = scoping.caller(Some("not-a-key")).await;
+        let missing = scoping.caller(None).await;
+
+        // Then
+        let users = UserListing::all(&pool).await.unwrap();
+        let test_admin = users.iter().find(|user| user.username == "test_admin").unwrap();
+        assert!(matches!(user, Caller::User { user_id, .. } if user_id == test_admin.id));
+        assert_eq!(admin, Caller::Admin);
+        assert_eq!(forged, Caller::Anonymous);
+        assert_eq!(missing, Caller::Anonymous);
+    }
+}
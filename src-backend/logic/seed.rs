@@ -0,0 +1,112 @@
+use crate::model::{UserAll, UserRegistration, UserRole};
+use color_eyre::eyre::{WrapErr, bail, eyre};
+use serde::Deserialize;
+use sqlx::SqlitePool;
+use std::path::Path;
+use tracing::{debug, info};
+use validator::Validate;
+
+/// Entities of a fixture set, each file contributing to any of the lists
+#[derive(Deserialize, Debug, Default)]
+#[serde(default, deny_unknown_fields)]
+pub struct Fixtures {
+    pub users: Vec<UserFixture>,
+}
+
+#[derive(Deserialize, Debug)]
+pub struct UserFixture {
+    #[serde(flatten)]
+    pub user: UserRegistration,
+    #[serde(default)]
+    pub role: UserRole,
+}
+
+/// Counts of a seeding run, existing entities being skipped
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct SeedReport {
+    pub inserted: usize,
+    pub skipped: usize,
+}
+
+impl Fixtures {
+    /// Reads every `.yaml`, `.yml` and `.json` file of a fixture set directory, in file name order
+    pub fn load(dir: &Path) -> crate::Result<Self> {
+        let mut files: Vec<_> = std::fs::read_dir(dir)
+            .wrap_err_with(|| format!("Failed to read fixture set {}", dir.display()))?
+            .map(|entry| entry.map(|entry| entry.path()))
+            .collect::<Result<_, _>>()?;
+        files.sort();
+
+        let mut fixtures = Self::default();
+        for path in files {
+            let content = || std::fs::read_to_string(&path);
+            let file: Self = match path.extension().and_then(|ext| ext.to_str()) {
+                Some("yaml" | "yml") => serde_yaml::from_str(&content()?).map_err(|err| eyre!(err)),
+                Some("json") => serde_json::from_str(&content()?).map_err(|err| eyre!(err)),
+                _ => continue,
+            }
+            .wrap_err_with(|| format!("Invalid fixture file {}", path.display()))?;
+            fixtures.users.extend(file.users);
+        }
+        Ok(fixtures)
+    }
+
+    /// Inserts the fixtures through the model insert paths, skipping those already present
+    pub async fn seed(&self, pool: &SqlitePool) -> crate::Result<SeedReport> {
+        let mut report = SeedReport::default();
+        let mut tx = pool.begin().await?;
+        for UserFixture { user, role } in &self.users {
+            if let Err(err) = user.validate() {
+                bail!("Invalid fixture user {}: {err}", user.username);
+            }
+            if UserAll::exists(&mut *tx, &user.username).await? {
+                debug!("Fixture user {} exists, skipping", user.username);
+                report.skipped += 1;
+                continue;
+            }
+            user.insert(&mut *tx).await?;
+            UserAll::set_role(&mut *tx, &user.username, *role).await?;
+            report.inserted += 1;
+        }
+        tx.commit().await?;
+        info!("Seeded {} fixtures, {} already present", report.inserted, report.skipped);
+        Ok(report)
+    }
+}
+
+/// Migrated in-memory database seeded with a fixture set of the repository's `fixtures` directory
+#[cfg(test)]
+pub async fn seeded_database(set: &str) -> crate::Result<SqlitePool> {
+    let pool = crate::logic::test_database().await?;
+    let dir = Path::new(env!("CARGO_MANIFEST_DIR")).join("fixtures").join(set);
+    Fixtures::load(&dir)?.seed(&pool).await?;
+    Ok(pool)
+}
+
+#[cfg(test)]
+mod test {
+    use std::path::Path;
+
+    use super::{Fixtures, SeedReport, seeded_database};
+    use crate::model::{UserListing, UserRole};
+
+    #[tokio::test]
+    async fn seed_given_seeded_database_then_nothing_inserted_twice() {
+        // Given
+        let pool = seeded_database("test").await.unwrap();
+        let fixtures = Fixtures::load(&Path::new(env!("CARGO_MANIFEST_DIR")).join("fixtures/test")).unwrap();
+
+        // When
+        let report = fixtures.seed(&pool).await.unwrap();
+
+        // Then
+        assert_eq!(report, SeedReport { inserted: 0, skipped: 2 });
+        let users = UserListing::all(&pool).await.unwrap();
+        assert_eq!(users.len(), 2);
+        assert!(
+            users
+                .iter()
+                .any(|user| user.username == "test_admin" && user.role == UserRole::Admin)
+        );
+    }
+}
@@ -0,0 +1,79 @@
+use crate::model::{ErrorReason, ErrorResponse, Secret, UserAll};
+use sqlx::SqlitePool;
+use tokio::sync::{Mutex, MutexGuard};
+use tracing::warn;
+use uuid::Uuid;
+
+/// One-time token of the `setup` mutation creating the first admin, present only while the database has no admin
+#[derive(Debug, Default)]
+pub struct SetupToken(Mutex<Option<Secret>>);
+
+impl SetupToken {
+    /// Issues a token when the database has no admin yet, printing it to the log
+    pub async fn for_database(pool: &SqlitePool) -> crate::Result<Self> {
+        if UserAll::admin_exists(pool).await? {
+            return Ok(Self::default());
+        }
+        let token = Uuid::new_v4().simple().to_string();
+        warn!("No admin yet, create the first admin with the `setup` mutation and the one-time setup token {token}");
+        Ok(Self(Mutex::new(Some(Secret::from(token)))))
+    }
+
+    /// Locks the token once it matches, serializing setups so only one can consume it
+    pub async fn redeem(&self, given: &str) -> Result<MutexGuard<'_, Option<Secret>>, ErrorResponse> {
+        let token = self.0.lock().await;
+        match token.as_ref() {
+            None => Err(ErrorResponse::new(ErrorReason::Forbidden, "Setup is already done".into())),
+            Some(expected) if expected.matches(given) => Ok(token),
+            Some(_) => Err(ErrorResponse::new(ErrorReason::Unauthorized, "Invalid setup token".into())),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::SetupToken;
+    use crate::{
+        logic::seeded_database,
+        model::{ErrorReason, UserRegistration},
+    };
+
+    #[tokio::test]
+    async fn redeem_given_consumed_token_then_forbidden() {
+        // Given
+        let pool = crate::logic::test_database().await.unwrap();
+        let setup = SetupToken::for_database(&pool).await.unwrap();
+        let token = setup.0.lock().await.as_ref().unwrap().expose().to_string();
+
+        // When
+        let wrong = setup.redeem("guess").await.map(|_| ()).map_err(|err| err.reason);
+        setup.redeem(&token).await.unwrap().take();
+        let again = setup.redeem(&token).await.map(|_| ()).map_err(|err| err.reason);
+
+        // Then
+        assert_eq!(wrong, Err(ErrorReason::Unauthorized));
+        assert_eq!(again, Err(ErrorReason::Forbidden));
+    }
+
+    #[tokio::test]
+    async fn for_database_given_users_without_admin_then_token_issued() {
+        // Given
+        let pool = crate::logic::test_database().await.unwrap();
+        let user = UserRegistration {
+            username: "early_bird".into(),
+            email: "early@example.com".into(),
+            password: "hunter22".into(),
+        };
+        user.insert(&pool).await.unwrap();
+
+        // When
+        let setup = SetupToken::for_database(&pool).await.unwrap();
+        let seeded = SetupToken::for_database(&seeded_database("test").await.unwrap())
+            .await
+            .unwrap();
+
+        // Then
+        assert!(setup.0.lock().await.is_some());
+        assert!(seeded.0.lock().await.is_none());
+    }
+}
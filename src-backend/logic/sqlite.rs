@@ -1,26 +1,268 @@
+use color_eyre::eyre::{WrapErr, bail};
 use sqlx::{
-    SqlitePool,
-    migrate::{MigrateDatabase as _, Migrator},
+    Connection, Row, SqliteConnection, SqlitePool,
+    migrate::{Migrate as _, Migrator},
+    sqlite::{SqliteConnectOptions, SqliteJournalMode, SqliteLockingMode, SqlitePoolOptions, SqliteSynchronous},
 };
+use std::{path::Path, time::Duration};
+use tracing::{info, warn};
 
-use crate::model::Config;
+use crate::model::{Config, JournalMode, Synchronous};
 
 // Embed migrations at compile time
 static MIGRATOR: Migrator = sqlx::migrate!("./migrations");
 
+/// Embedded migration and whether it is applied to the database
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MigrationStatus {
+    pub version: i64,
+    pub description: String,
+    pub is_applied: bool,
+    pub is_reversible: bool,
+}
+
 /// Creates DB -> Run Migrations -> Creates Connect Pool
 pub async fn setup_database(config: &Config) -> crate::Result<SqlitePool> {
-    let db_url = config.database.to_string_lossy();
-
-    if !sqlx::Sqlite::database_exists(&db_url).await? {
-        sqlx::Sqlite::create_database(&db_url).await?;
-    }
-
-    // Connect to the database
-    let db = SqlitePool::connect(&db_url).await?;
+    let db = connect_database(config).await?;
 
     // Run embedded migrations
     MIGRATOR.run(&db).await?;
 
     Ok(db)
 }
+
+/// Creates DB -> Creates Connect Pool, leaving migrations to the caller
+pub async fn connect_database(config: &Config) -> crate::Result<SqlitePool> {
+    if let Some(dir) = config.database.parent().filter(|dir| !dir.as_os_str().is_empty()) {
+        std::fs::create_dir_all(dir)?;
+    }
+
+    let idle_timeout = (config.database_idle_timeout > 0).then(|| Duration::from_secs(config.database_idle_timeout));
+    let pool = SqlitePoolOptions::new()
+        .min_connections(config.database_pool_min)
+        .max_connections(config.database_pool_max)
+        .acquire_timeout(Duration::from_secs(config.database_acquire_timeout))
+        .idle_timeout(idle_timeout)
+        .connect_with(connect_options(config))
+        .await?;
+
+    log_pragmas(config, &pool).await?;
+    Ok(pool)
+}
+
+fn connect_options(config: &Config) -> SqliteConnectOptions {
+    SqliteConnectOptions::new()
+        .filename(&config.database)
+        .create_if_missing(true)
+        .journal_mode(match config.database_journal_mode {
+            JournalMode::Delete => SqliteJournalMode::Delete,
+            JournalMode::Truncate => SqliteJournalMode::Truncate,
+            JournalMode::Persist => SqliteJournalMode::Persist,
+            JournalMode::Memory => SqliteJournalMode::Memory,
+            JournalMode::Wal => SqliteJournalMode::Wal,
+            JournalMode::Off => SqliteJournalMode::Off,
+        })
+        .synchronous(match config.database_synchronous {
+            Synchronous::Off => SqliteSynchronous::Off,
+            Synchronous::Normal => SqliteSynchronous::Normal,
+            Synchronous::Full => SqliteSynchronous::Full,
+            Synchronous::Extra => SqliteSynchronous::Extra,
+        })
+        .busy_timeout(Duration::from_millis(config.database_busy_timeout_ms))
+        .foreign_keys(config.database_foreign_keys)
+        // Negative sizes are in KiB rather than pages
+        .pragma("cache_size", format!("-{}", config.database_cache_size_kib))
+}
+
+/// Logs the pragmas in effect, warning when SQLite refused the journal mode (e.g. WAL on a network filesystem)
+async fn log_pragmas(config: &Config, pool: &SqlitePool) -> crate::Result {
+    let mut conn = pool.acquire().await?;
+    let mut pragmas = Vec::new();
+    for pragma in ["journal_mode", "synchronous", "busy_timeout", "foreign_keys", "cache_size"] {
+        let row = sqlx::query(&format!("PRAGMA {pragma}")).fetch_one(&mut *conn).await?;
+        // Integer pragmas read as text, SQLite converting them
+        let value: String = row.try_get_unchecked(0)?;
+        pragmas.push((pragma, value));
+    }
+    let summary: Vec<String> = pragmas.iter().map(|(pragma, value)| format!("{pragma}={value}")).collect();
+    info!(
+        "SQLite {} with {}, pool of {} to {} connections",
+        config.database.display(),
+        summary.join(" "),
+        config.database_pool_min,
+        config.database_pool_max
+    );
+
+    let configured = format!("{:?}", config.database_journal_mode).to_lowercase();
+    if pragmas[0].1.to_lowercase() != configured {
+        warn!("SQLite refused journal mode {configured}, using {}", pragmas[0].1);
+    }
+    Ok(())
+}
+
+/// Migrated in-memory database, a single connection keeping it alive
+#[cfg(test)]
+pub async fn test_database() -> crate::Result<SqlitePool> {
+    let pool = sqlx::sqlite::SqlitePoolOptions::new()
+        .max_connections(1)
+        .idle_timeout(None)
+        .max_lifetime(None)
+        .connect("sqlite::memory:")
+        .await?;
+    MIGRATOR.run(&pool).await?;
+    Ok(pool)
+}
+
+/// Applies every pending migration
+pub async fn migrate_up(pool: &SqlitePool) -> crate::Result {
+    MIGRATOR.run(pool).await?;
+    Ok(())
+}
+
+/// Reverts the latest applied migration, which must be reversible (`.up.sql` and `.down.sql`)
+pub async fn migrate_down(pool: &SqlitePool) -> crate::Result<Option<MigrationStatus>> {
+    let applied: Vec<MigrationStatus> = migration_status(pool).await?.into_iter().filter(|m| m.is_applied).collect();
+    let Some(latest) = applied.last() else {
+        return Ok(None);
+    };
+    if !latest.is_reversible {
+        bail!("Migration {} ({}) is not reversible", latest.version, latest.description);
+    }
+
+    let target = applied.iter().rev().nth(1).map_or(0, |previous| previous.version);
+    MIGRATOR.undo(pool, target).await?;
+    Ok(Some(latest.clone()))
+}
+
+/// Every embedded migration with its applied state, oldest first
+pub async fn migration_status(pool: &SqlitePool) -> crate::Result<Vec<MigrationStatus>> {
+    let applied = applied_migrations(pool).await?;
+
+    Ok(MIGRATOR
+        .iter()
+        .filter(|migration| !migration.migration_type.is_down_migration())
+        .map(|migration| MigrationStatus {
+            version: migration.version,
+            description: migration.description.to_string(),
+            is_applied: applied.contains(&migration.version),
+            is_reversible: MIGRATOR
+                .iter()
+                .any(|down| down.version == migration.version && down.migration_type.is_down_migration()),
+        })
+        .collect())
+}
+
+/// Versions applied to the database but not embedded, i.e. by a newer build
+pub async fn unknown_migrations(pool: &SqlitePool) -> crate::Result<Vec<i64>> {
+    Ok(applied_migrations(pool)
+        .await?
+        .into_iter()
+        .filter(|version| MIGRATOR.iter().all(|migration| migration.version != *version))
+        .collect())
+}
+
+/// Versions applied to the database, read without creating the migrations table so read-only connections work
+async fn applied_migrations(pool: &SqlitePool) -> crate::Result<Vec<i64>> {
+    let mut conn = pool.acquire().await?;
+    let is_tracked = sqlx::query("SELECT 1 FROM sqlite_master WHERE type = 'table' AND name = '_sqlx_migrations'")
+        .fetch_optional(&mut *conn)
+        .await?
+        .is_some();
+    // No migrations table yet means none applied, all of them pending
+    if !is_tracked {
+        return Ok(Vec::new());
+    }
+    Ok(conn
+        .list_applied_migrations()
+        .await?
+        .into_iter()
+        .map(|applied| applied.version)
+        .collect())
+}
+
+/// Writes a consistent copy of the live database, safe while the server is running
+pub async fn backup_database(pool: &SqlitePool, backup: &Path) -> crate::Result {
+    if backup.exists() {
+        bail!("Backup {} already exists", backup.display());
+    }
+    let backup = backup.to_string_lossy();
+    sqlx::query("VACUUM INTO $1").bind(backup.as_ref()).execute(pool).await?;
+    Ok(())
+}
+
+/// Replaces the database with a backup after checking its integrity.
+/// Restoring under a running server is unsafe as its open connections keep writing to the replaced file,
+/// so the restore is refused while another connection holds the database.
+pub async fn restore_database(config: &Config, backup: &Path) -> crate::Result {
+    let options = SqliteConnectOptions::new().filename(backup).read_only(true);
+    let mut conn = SqliteConnection::connect_with(&options).await?;
+    let integrity: String = sqlx::query_scalar("PRAGMA integrity_check").fetch_one(&mut conn).await?;
+    conn.close().await?;
+    if integrity != "ok" {
+        bail!("Backup {} is corrupt: {integrity}", backup.display());
+    }
+
+    if config.database.exists() {
+        ensure_unused(&config.database)
+            .await
+            .wrap_err_with(|| format!("{} is in use, stop the server before restoring", config.database.display()))?;
+    }
+
+    // Stale write-ahead log of the replaced database would be replayed onto the backup
+    for suffix in ["-wal", "-shm"] {
+        let mut sidecar = config.database.clone().into_os_string();
+        sidecar.push(suffix);
+        let _ = std::fs::remove_file(sidecar);
+    }
+    std::fs::copy(backup, &config.database)?;
+    info!("Restored {} from {}", config.database.display(), backup.display());
+    Ok(())
+}
+
+/// Takes an exclusive lock without waiting, failing while any other connection has the database open.
+/// In WAL mode connections hold a shared lock for as long as they are open, even when idle.
+async fn ensure_unused(database: &Path) -> crate::Result {
+    let options = SqliteConnectOptions::new()
+        .filename(database)
+        .locking_mode(SqliteLockingMode::Exclusive)
+        .busy_timeout(Duration::ZERO);
+    let mut conn = SqliteConnection::connect_with(&options).await?;
+    sqlx::query("BEGIN EXCLUSIVE").execute(&mut conn).await?;
+    sqlx::query("ROLLBACK").execute(&mut conn).await?;
+    conn.close().await?;
+    Ok(())
+}
+
+/// Rebuilds the database file, reclaiming the space of deleted rows
+pub async fn vacuum_database(pool: &SqlitePool) -> crate::Result {
+    sqlx::query("VACUUM").execute(pool).await?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use clap::Parser;
+    use uuid::Uuid;
+
+    use super::connect_database;
+    use crate::model::Config;
+
+    #[tokio::test]
+    async fn connect_database_given_defaults_then_wal_and_foreign_keys() {
+        // Given
+        let dir = std::env::temp_dir().join(format!("breezi-{}", Uuid::new_v4()));
+        let database = dir.join("test.db");
+        let config = Config::parse_from([env!("CARGO_PKG_NAME"), "--database", database.to_str().unwrap()]);
+
+        // When
+        let pool = connect_database(&config).await.unwrap();
+
+        // Then
+        let journal_mode: String = sqlx::query_scalar("PRAGMA journal_mode").fetch_one(&pool).await.unwrap();
+        let foreign_keys: i64 = sqlx::query_scalar("PRAGMA foreign_keys").fetch_one(&pool).await.unwrap();
+        assert_eq!(journal_mode, "wal");
+        assert_eq!(foreign_keys, 1);
+        pool.close().await;
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+}
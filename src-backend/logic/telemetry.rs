@@ -0,0 +1,378 @@
+use crate::{
+    logic::{record_handler, request_id},
+    model::{Config, ErrorReason, ErrorResponse, LogFormat, LogRotation, handler_override},
+    routes::RequestCtx,
+};
+use axum::http::Request;
+use color_eyre::eyre::eyre;
+use opentelemetry::{global, trace::TracerProvider as _};
+use opentelemetry_http::HeaderExtractor;
+use opentelemetry_otlp::{SpanExporter, WithExportConfig};
+use opentelemetry_sdk::{Resource, propagation::TraceContextPropagator, trace::SdkTracerProvider};
+use std::{
+    sync::OnceLock,
+    time::{Duration, Instant},
+};
+use tracing::{Instrument, Span, Subscriber, field, info_span};
+use tracing_appender::{
+    non_blocking::WorkerGuard,
+    rolling::{RollingFileAppender, Rotation},
+};
+use tracing_opentelemetry::OpenTelemetrySpanExt;
+use tracing_subscriber::{
+    EnvFilter, Layer, Registry, fmt::MakeWriter, layer::SubscriberExt, registry::LookupSpan, reload, util::SubscriberInitExt,
+};
+
+static LOG_FILTER: OnceLock<reload::Handle<EnvFilter, Registry>> = OnceLock::new();
+
+/// Keeps log file writers and the OTLP exporter alive, flushing them on [`TracingGuard::shutdown`]
+pub struct TracingGuard {
+    tracer_provider: Option<SdkTracerProvider>,
+    _log_file: Option<WorkerGuard>,
+}
+
+impl TracingGuard {
+    pub fn shutdown(self) -> crate::Result {
+        if let Some(provider) = self.tracer_provider {
+            provider.shutdown()?;
+        }
+        Ok(())
+    }
+}
+
+/// Installs the global tracing subscriber from the `log_*` and `otel_*` settings.
+/// Spans are exported over OTLP when an endpoint is configured.
+pub fn init_tracing(config: &Config) -> crate::Result<TracingGuard> {
+    let (filter, filter_handle) = reload::Layer::new(EnvFilter::try_new(&config.log_filter)?);
+    let _ = LOG_FILTER.set(filter_handle);
+
+    let mut log_layers = vec![fmt_layer(config.log_format, std::io::stdout, true)];
+    let log_file = match &config.log_dir {
+        Some(dir) => {
+            let appender = RollingFileAppender::builder()
+                .rotation(match config.log_rotation {
+                    LogRotation::Minutely => Rotation::MINUTELY,
+                    LogRotation::Hourly => Rotation::HOURLY,
+                    LogRotation::Daily => Rotation::DAILY,
+                    LogRotation::Never => Rotation::NEVER,
+                })
+                .filename_prefix(env!("CARGO_PKG_NAME"))
+                .filename_suffix("log")
+                .max_log_files(config.log_max_files)
+                .build(dir)?;
+            let (writer, guard) = tracing_appender::non_blocking(appender);
+            log_layers.push(fmt_layer(config.log_format, writer, false));
+            Some(guard)
+        }
+        None => None,
+    };
+
+    let tracer_provider = config
+        .otel_endpoint
+        .as_deref()
+        .map(|endpoint| tracer_provider(endpoint, &config.otel_service_name))
+        .transpose()?;
+    let otel_layer = tracer_provider
+        .as_ref()
+        .map(|provider| tracing_opentelemetry::layer().with_tracer(provider.tracer(env!("CARGO_PKG_NAME"))));
+
+    tracing_subscriber::registry()
+        .with(filter)
+        .with(log_layers)
+        .with(otel_layer)
+        .init();
+
+    global::set_text_map_propagator(TraceContextPropagator::new());
+    if let Some(provider) = &tracer_provider {
+        global::set_tracer_provider(provider.clone());
+    }
+
+    Ok(TracingGuard {
+        tracer_provider,
+        _log_file: log_file,
+    })
+}
+
+/// Replaces the active log filter directives without a restart
+pub fn set_log_filter(directives: &str) -> crate::Result {
+    let handle = LOG_FILTER.get().ok_or_else(|| eyre!("tracing is not initialised"))?;
+    reload_filter(handle, directives)
+}
+
+/// Parses the directives before swapping, so invalid ones leave the active filter in place
+fn reload_filter<S>(handle: &reload::Handle<EnvFilter, S>, directives: &str) -> crate::Result {
+    handle.reload(EnvFilter::try_new(directives)?)?;
+    Ok(())
+}
+
+fn fmt_layer<S, W>(format: LogFormat, writer: W, ansi: bool) -> Box<dyn Layer<S> + Send + Sync>
+where
+    S: Subscriber + for<'span> LookupSpan<'span>,
+    W: for<'writer> MakeWriter<'writer> + Send + Sync + 'static,
+{
+    let layer = tracing_subscriber::fmt::layer().with_writer(writer).with_ansi(ansi);
+    match format {
+        LogFormat::Full => layer.boxed(),
+        LogFormat::Pretty => layer.pretty().boxed(),
+        LogFormat::Compact => layer.compact().boxed(),
+        LogFormat::Json => layer.json().boxed(),
+    }
+}
+
+fn tracer_provider(endpoint: &str, service_name: &str) -> crate::Result<SdkTracerProvider> {
+    let exporter = SpanExporter::builder().with_http().with_endpoint(endpoint).build()?;
+    let provider = SdkTracerProvider::builder()
+        .with_batch_exporter(exporter)
+        .with_resource(Resource::builder().with_service_name(service_name.to_string()).build())
+        .build();
+    Ok(provider)
+}
+
+/// Span for an incoming HTTP request, parented to the caller's W3C `traceparent` when present
+pub fn http_span<B>(req: &Request<B>) -> Span {
+    let request_id = request_id(req).unwrap_or_default();
+    let span = info_span!(
+        "http_request",
+        request_id = %request_id,
+        otel.name = %format_args!("{} {}", req.method(), req.uri().path()),
+        http.request.method = %req.method(),
+        url.path = req.uri().path(),
+    );
+    let parent = global::get_text_map_propagator(|propagator| propagator.extract(&HeaderExtractor(req.headers())));
+    span.set_parent(parent);
+    span
+}
+
+/// Wraps a qubit query handler body, see [`track`]
+pub async fn track_handler<T>(
+    ctx: &RequestCtx,
+    handler: &'static str,
+    body: impl Future<Output = Result<T, ErrorResponse>>,
+) -> Result<T, ErrorResponse> {
+    track(ctx, handler, false, body).await
+}
+
+/// Wraps a qubit mutation handler body, rejected while in read-only maintenance, see [`track`]
+pub async fn track_mutation<T>(
+    ctx: &RequestCtx,
+    handler: &'static str,
+    body: impl Future<Output = Result<T, ErrorResponse>>,
+) -> Result<T, ErrorResponse> {
+    track(ctx, handler, true, body).await
+}
+
+/// Wraps a qubit handler body in its own span after enforcing maintenance mode, its rate limit and timeout,
+/// recording call count and latency labelled by outcome
+async fn track<T>(
+    ctx: &RequestCtx,
+    handler: &'static str,
+    is_mutation: bool,
+    body: impl Future<Output = Result<T, ErrorResponse>>,
+) -> Result<T, ErrorResponse> {
+    let started = Instant::now();
+    let span = info_span!(
+        "rpc_handler",
+        otel.name = handler,
+        rpc.method = handler,
+        outcome = field::Empty,
+        client.address = field::Empty,
+    );
+    if let Some(ip) = ctx.client_ip() {
+        span.record("client.address", field::display(ip));
+    }
+    let live = ctx.live.load();
+    let result = async {
+        ctx.maintenance.check(handler, is_mutation)?;
+        live.rate_limits.check_handler(handler, &ctx.request)?;
+        match handler_override(&live.config.request_timeout_handlers, handler) {
+            Some(secs) => tokio::time::timeout(Duration::from_secs(secs), body)
+                .await
+                .map_err(|_| ErrorResponse::new(ErrorReason::GatewayTimeout, format!("Handler timed out after {secs}s")))?,
+            None => body.await,
+        }
+    }
+    .instrument(span.clone())
+    .await;
+
+    let outcome = match &result {
+        Ok(_) => "Ok",
+        Err(err) => err.reason.name(),
+    };
+    span.record("outcome", outcome);
+    record_handler(handler, outcome, started);
+
+    result
+}
+
+#[cfg(test)]
+mod test {
+    use axum::{body::Bytes, extract::State, http::Request, routing::post};
+    use opentelemetry::{global, trace::TracerProvider as _};
+    use opentelemetry_proto::tonic::collector::trace::v1::ExportTraceServiceRequest;
+    use opentelemetry_sdk::propagation::TraceContextPropagator;
+    use prost::Message;
+    use std::{
+        io::{self, Write},
+        sync::{Arc, Mutex},
+    };
+    use tokio::{net::TcpListener, sync::mpsc};
+    use tracing_subscriber::{EnvFilter, Registry, fmt::MakeWriter, layer::SubscriberExt, reload};
+
+    use clap::Parser;
+    use sqlx::SqlitePool;
+
+    use super::{fmt_layer, http_span, reload_filter, tracer_provider, track_handler};
+    use crate::{
+        logic::RequestScope,
+        model::{Config, ErrorResponse, LogFormat},
+        routes::{Ctx, RequestCtx},
+    };
+
+    type ExportedSpan = (String, Vec<u8>);
+
+    /// Log writer keeping every written byte for inspection
+    #[derive(Clone, Default)]
+    struct Captured(Arc<Mutex<Vec<u8>>>);
+
+    impl Write for Captured {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            self.0.lock().unwrap().extend_from_slice(buf);
+            Ok(buf.len())
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    impl MakeWriter<'_> for Captured {
+        type Writer = Self;
+
+        fn make_writer(&self) -> Self::Writer {
+            self.clone()
+        }
+    }
+
+    fn log_line(format: LogFormat) -> String {
+        let captured = Captured::default();
+        let subscriber = tracing_subscriber::registry().with(fmt_layer(format, captured.clone(), false));
+        tracing::subscriber::with_default(subscriber, || tracing::info!(answer = 42, "hello"));
+        String::from_utf8(captured.0.lock().unwrap().clone()).unwrap()
+    }
+
+    #[test]
+    fn reload_filter_given_invalid_directives_then_rejected_and_filter_kept() {
+        // Given
+        let (_filter, handle) = reload::Layer::<_, Registry>::new(EnvFilter::new("info"));
+
+        // When
+        let invalid = reload_filter(&handle, "breezi=loud");
+
+        // Then
+        assert!(invalid.is_err());
+        assert_eq!(handle.with_current(ToString::to_string).unwrap(), "info");
+        reload_filter(&handle, "breezi=debug").unwrap();
+        assert_eq!(handle.with_current(ToString::to_string).unwrap(), "breezi=debug");
+    }
+
+    #[test]
+    fn fmt_layer_given_json_format_then_one_json_object_per_event() {
+        // Given
+        let formats = (LogFormat::Json, LogFormat::Compact);
+
+        // When
+        let (json, compact) = (log_line(formats.0), log_line(formats.1));
+
+        // Then
+        let json: serde_json::Value = serde_json::from_str(json.trim()).unwrap();
+        assert_eq!(json["level"], "INFO");
+        assert_eq!(json["fields"]["message"], "hello");
+        assert_eq!(json["fields"]["answer"], 42);
+        assert!(serde_json::from_str::<serde_json::Value>(compact.trim()).is_err());
+        assert!(compact.contains("hello answer=42"));
+    }
+
+    /// Minimal OTLP/HTTP collector, forwarding every received span name with its trace id
+    async fn collector() -> (String, mpsc::UnboundedReceiver<ExportedSpan>) {
+        let (tx, rx) = mpsc::unbounded_channel();
+        let router = axum::Router::new()
+            .route(
+                "/v1/traces",
+                post(
+                    |State(tx): State<mpsc::UnboundedSender<ExportedSpan>>, body: Bytes| async move {
+                        let request = ExportTraceServiceRequest::decode(body).expect("collector should decode OTLP payload");
+                        for span in request
+                            .resource_spans
+                            .into_iter()
+                            .flat_map(|resource| resource.scope_spans)
+                            .flat_map(|scope| scope.spans)
+                        {
+                            let _ = tx.send((span.name, span.trace_id));
+                        }
+                    },
+                ),
+            )
+            .with_state(tx);
+
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let endpoint = format!("http://{}/v1/traces", listener.local_addr().unwrap());
+        tokio::spawn(async move { axum::serve(listener, router).await });
+        (endpoint, rx)
+    }
+
+    #[test]
+    fn track_handler_given_traceparent_then_span_exported_under_caller_trace() {
+        // Given
+        let runtime = tokio::runtime::Runtime::new().unwrap();
+        let (endpoint, mut received) = runtime.block_on(collector());
+        let provider = tracer_provider(&endpoint, "breezi-test").unwrap();
+        let subscriber = tracing_subscriber::registry().with(tracing_opentelemetry::layer().with_tracer(provider.tracer("test")));
+        global::set_text_map_propagator(TraceContextPropagator::new());
+        let ctx = {
+            let _runtime = runtime.enter();
+            RequestCtx {
+                ctx: Ctx::new(
+                    Config::parse_from([env!("CARGO_PKG_NAME")]),
+                    SqlitePool::connect_lazy("sqlite::memory:").unwrap(),
+                ),
+                request: RequestScope::default(),
+            }
+        };
+
+        let trace_id = "4bf92f3577b34da6a3ce929d0e0e4736";
+        let request = Request::post("/rpc")
+            .header("traceparent", format!("00-{trace_id}-00f067aa0ba902b7-01"))
+            .body(())
+            .unwrap();
+
+        // When
+        tracing::subscriber::with_default(subscriber, || {
+            let span = http_span(&request);
+            let _entered = span.enter();
+            runtime
+                .block_on(track_handler(&ctx, "register", async { Ok::<_, ErrorResponse>(()) }))
+                .unwrap();
+        });
+        provider.force_flush().expect("spans should flush to collector");
+
+        // Then
+        let mut spans = Vec::new();
+        while spans.len() < 2 {
+            spans.push(runtime.block_on(received.recv()).expect("collector should receive spans"));
+        }
+        spans.sort();
+
+        let trace_id = (0..trace_id.len())
+            .step_by(2)
+            .map(|i| u8::from_str_radix(&trace_id[i..i + 2], 16).unwrap())
+            .collect::<Vec<_>>();
+        assert_eq!(
+            spans,
+            vec![
+                ("POST /rpc".to_string(), trace_id.clone()),
+                ("register".to_string(), trace_id)
+            ]
+        );
+        provider.shutdown().unwrap();
+    }
+}
@@ -2,7 +2,8 @@ use derive_more::{Deref, DerefMut};
 use regex::Regex;
 use serde::{Deserialize, Serialize};
 use serde_json::Value;
-use std::{collections::HashMap, sync::LazyLock};
+use std::{collections::HashMap, fs::OpenOptions, path::Path, sync::LazyLock};
+use uuid::Uuid;
 use validator::{Validate, ValidationErrors};
 
 /// `#[validate(regex(path = *REGEX_USERNAME, code = "username"))]`
@@ -12,6 +13,16 @@ pub static REGEX_USERNAME: LazyLock<Regex> = LazyLock::new(|| Regex::new(PATTERN
 pub static PATTERN_UUID: &str = r"^[0-9a-fA-F]{8}-[0-9a-fA-F]{4}-4[0-9a-fA-F]{3}-[89abAB][0-9a-fA-F]{3}-[0-9a-fA-F]{12}$";
 pub static REGEX_UUID: LazyLock<Regex> = LazyLock::new(|| Regex::new(PATTERN_UUID).unwrap());
 
+/// Whether a file can be created in the directory, probed by creating and removing one
+/// since permission bits miss ACLs, read-only mounts and root
+pub fn is_dir_writable(dir: &Path) -> bool {
+    let probe = dir.join(format!(".{}-probe-{}", env!("CARGO_PKG_NAME"), Uuid::new_v4().simple()));
+    match OpenOptions::new().write(true).create_new(true).open(&probe) {
+        Ok(_) => std::fs::remove_file(&probe).is_ok(),
+        Err(_) => false,
+    }
+}
+
 #[allow(dead_code)] // Keep it here, it works but then you have to use try/catch in js land
 #[derive(Debug, Deref, DerefMut, Clone)]
 struct Validated<T: Clone>(T);
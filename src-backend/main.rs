@@ -2,34 +2,61 @@ mod logic;
 mod model;
 mod routes;
 
-use crate::{logic::setup_database, model::Config, routes::Routes};
+use crate::{
+    logic::{SetupToken, bind_listeners, init_tracing, install_metrics, run_command, serve_all, setup_database, watch_config},
+    model::{Cli, Command, ConfigCommand},
+    routes::Routes,
+};
 use color_eyre::eyre::Report;
 use std::net::SocketAddr;
 use tokio::net::TcpListener;
-use tracing::info;
-use tracing_subscriber::{layer::SubscriberExt, util::SubscriberInitExt};
+use tracing::{info, warn};
 
 pub type Result<T = (), E = Report> = std::result::Result<T, E>;
 
 #[tokio::main]
 async fn main() -> Result {
-    tracing_subscriber::registry().with(tracing_subscriber::fmt::layer()).init();
     color_eyre::install()?;
+    let cli = Cli::load()?;
+    // Diagnostics report an invalid config themselves, so run before tracing fails on it, e.g. on a bad `log_filter`
+    if let Some(command @ (Command::Doctor | Command::Config(ConfigCommand::Print | ConfigCommand::Init { .. }))) = cli.command {
+        return run_command(&cli.config, command).await;
+    }
+    cli.check()?; // Before any side effect, e.g. creating the log directory
+    let plain_secrets = cli.plain_secret_flags();
+    let Cli { config, command, .. } = cli;
+    let tracing = init_tracing(&config)?;
+    for secret in plain_secrets {
+        warn!(
+            "{secret} was passed as a command line flag, prefer its env var or {secret}_file to keep it out of the process list"
+        );
+    }
+
+    if let Some(command) = command.filter(|command| !matches!(command, Command::Serve)) {
+        let result = run_command(&config, command).await;
+        tracing.shutdown()?;
+        return result;
+    }
 
     info!("Starting up...");
-    let config = Config::parse()?;
+    install_metrics(&config)?;
     crate::logic::generate_all_bindings(&config)?;
     let pool = setup_database(&config).await?;
-    let router = Routes::build(config.clone(), pool)?;
+    let setup = SetupToken::for_database(&pool).await?;
+    let router = Routes::build(config.clone(), pool, setup)?;
+    watch_config(router.live.clone());
+
+    if let (Some(metrics), Some(port)) = (router.metrics.clone(), config.metrics_port) {
+        info!("Starting metrics server on {}:{}", &config.server_host, port);
+        let listener = TcpListener::bind(&SocketAddr::from((config.server_host, port))).await?;
+        tokio::spawn(async move { axum::serve(listener, metrics).await });
+    }
 
-    info!("Starting web server on {}:{}", &config.server_host, &config.server_port);
-    axum::serve(
-        TcpListener::bind(&SocketAddr::from((config.server_host, config.server_port))).await?,
-        router.axum(),
-    )
-    .await?;
+    info!("Starting web server...");
+    serve_all(bind_listeners(&config).await?, router.axum()).await?;
 
     info!("Stopping...");
     router.stop_services()?;
+    tracing.shutdown()?;
     Ok(())
 }
@@ -0,0 +1,48 @@
+use crate::model::Secret;
+use sha2::{Digest, Sha256};
+use sqlx::{Executor, Sqlite};
+use tracing::instrument;
+use uuid::Uuid;
+
+/// API key issued to a user, only its SHA-256 hash is stored so the database never holds usable keys
+#[derive(Debug, Clone, PartialEq, Eq, sqlx::FromRow)]
+pub struct ApiKey {
+    pub id: String,
+    pub user_id: String,
+}
+
+impl ApiKey {
+    /// Issues a new key to a user, returning it the only time it is known, `None` when the user doesn't exist
+    #[instrument(name = "sqlx.query", skip_all, fields(db.system = "sqlite", db.operation = "INSERT", db.table = "api_key"))]
+    pub async fn issue(conn: impl Executor<'_, Database = Sqlite>, username: &str) -> crate::Result<Option<Secret>> {
+        let id = Uuid::new_v4().to_string();
+        let key = Uuid::new_v4().simple().to_string();
+        let key_hash = hash_key(&key);
+        let result = sqlx::query!(
+            "INSERT INTO api_key (id, user_id, key_hash)
+            SELECT $1, id, $2 FROM user WHERE username = $3",
+            id,
+            key_hash,
+            username
+        )
+        .execute(conn)
+        .await?;
+        Ok((result.rows_affected() > 0).then(|| Secret::from(key)))
+    }
+
+    /// Looks up the issued key a bearer token is, if any
+    #[instrument(name = "sqlx.query", skip_all, fields(db.system = "sqlite", db.operation = "SELECT", db.table = "api_key"))]
+    pub async fn verify(conn: impl Executor<'_, Database = Sqlite>, key: &str) -> crate::Result<Option<Self>> {
+        let key_hash = hash_key(key);
+        Ok(
+            sqlx::query_as!(ApiKey, "SELECT id, user_id FROM api_key WHERE key_hash = $1", key_hash)
+                .fetch_optional(conn)
+                .await?,
+        )
+    }
+}
+
+/// Hex SHA-256 of a key, random keys need no salt nor slow hashing unlike passwords
+fn hash_key(key: &str) -> String {
+    format!("{:x}", Sha256::digest(key.as_bytes()))
+}
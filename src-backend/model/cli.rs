@@ -0,0 +1,391 @@
+use crate::{
+    Result,
+    model::{CONFIG_PATH_DEFAULT, Config, MaintenanceMode, SECRETS, Secret, UserRole},
+};
+use clap::{ArgMatches, CommandFactory, FromArgMatches, Parser, Subcommand, parser::ValueSource};
+use color_eyre::eyre::{bail, eyre};
+use serde_json::Value;
+use std::{
+    collections::HashMap,
+    fmt::{self, Display},
+    path::{Path, PathBuf},
+};
+use validator::{Validate, ValidationErrorsKind};
+
+const PRECEDENCE: &str = "\
+Settings are layered, each layer overriding the previous ones:
+  1. Defaults
+  2. Config file (--config-path, YAML, TOML or JSON by extension)
+  3. Profile overlay (config.{profile}.{ext} next to the config file, selected by --config-profile)
+  4. Environment variables
+  5. Command line flags";
+
+/// Command line of the server, its [`Config`] and an optional command run instead of serving
+#[derive(Parser, Debug, Clone)]
+#[command(after_help = PRECEDENCE)]
+pub struct Cli {
+    #[command(flatten)]
+    pub config: Config,
+    #[command(subcommand)]
+    pub command: Option<Command>,
+    /// Where each setting's value came from, keyed by field name
+    #[arg(skip)]
+    pub sources: HashMap<String, ConfigSource>,
+}
+
+/// Layer a setting's value came from
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ConfigSource {
+    Default,
+    File(PathBuf),
+    Env(String),
+    Flag(String),
+}
+
+impl Display for ConfigSource {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ConfigSource::Default => write!(f, "default"),
+            ConfigSource::File(path) => write!(f, "file {}", path.display()),
+            ConfigSource::Env(name) => write!(f, "env {name}"),
+            ConfigSource::Flag(name) => write!(f, "flag --{name}"),
+        }
+    }
+}
+
+#[derive(Subcommand, Debug, Clone)]
+pub enum Command {
+    /// Runs the web server, the default without a command
+    Serve,
+    /// Manages database migrations
+    #[command(subcommand)]
+    Migrate(MigrateCommand),
+    /// Manages TypeScript bindings
+    #[command(subcommand)]
+    Bindings(BindingsCommand),
+    /// Manages users
+    #[command(subcommand)]
+    User(UserCommand),
+    /// Maintains the database file
+    #[command(subcommand)]
+    Db(DbCommand),
+    /// Loads a fixture set into the database, skipping entities already present
+    Seed {
+        /// Fixture set, a directory of `fixtures_dir`, defaults to `config_profile` or else `dev`
+        set: Option<String>,
+    },
+    /// Sets the maintenance mode of the running server through its admin RPC
+    Maintenance {
+        #[arg(value_enum)]
+        mode: MaintenanceMode,
+    },
+    /// Inspects the configuration
+    #[command(subcommand)]
+    Config(ConfigCommand),
+    /// Checks config, database, embedded SPA and listeners, exiting non-zero when any check fails
+    Doctor,
+}
+
+#[derive(Subcommand, Debug, Clone)]
+pub enum MigrateCommand {
+    /// Applies every pending migration
+    Up,
+    /// Reverts the latest applied migration
+    Down,
+    /// Lists migrations and whether they are applied
+    Status,
+}
+
+#[derive(Subcommand, Debug, Clone)]
+pub enum BindingsCommand {
+    /// Writes the RPC bindings and validation schemas to `bindings_dir`
+    Generate,
+}
+
+#[derive(Subcommand, Debug, Clone)]
+pub enum UserCommand {
+    /// Creates a user, reading the password from stdin unless given
+    Create {
+        username: String,
+        email: String,
+        #[arg(long, value_enum, default_value_t = UserRole::User)]
+        role: UserRole,
+        /// Password, prefer stdin to keep it out of the shell history
+        #[arg(long)]
+        password: Option<Secret>,
+    },
+    /// Sets the password of a user, reading it from stdin unless given
+    SetPassword {
+        username: String,
+        /// Password, prefer stdin to keep it out of the shell history
+        #[arg(long)]
+        password: Option<Secret>,
+    },
+    /// Sets the role of a user
+    SetRole {
+        username: String,
+        #[arg(value_enum)]
+        role: UserRole,
+    },
+    /// Issues an API key to a user, printed once and only stored hashed
+    IssueApiKey { username: String },
+    /// Lists every user
+    List,
+}
+
+#[derive(Subcommand, Debug, Clone)]
+pub enum DbCommand {
+    /// Writes a consistent copy of the database, safe while the server is running
+    Backup { path: PathBuf },
+    /// Replaces the database with a backup, the server must be stopped
+    Restore { path: PathBuf },
+    /// Rebuilds the database file, reclaiming unused space
+    Vacuum,
+}
+
+#[derive(Subcommand, Debug, Clone)]
+pub enum ConfigCommand {
+    /// Prints the merged configuration as YAML, secrets redacted
+    #[command(alias = "show")]
+    Print,
+    /// Validates the configuration, exiting non-zero when invalid
+    Check,
+    /// Writes a commented config file of every setting at its default
+    Init {
+        /// Where to write the file, defaults to `config_path`
+        path: Option<PathBuf>,
+        /// Overwrites an existing file
+        #[arg(long)]
+        force: bool,
+    },
+}
+
+impl Cli {
+    /// Parses the command line, merging the config file and its profile overlay beneath it
+    pub fn load() -> Result<Self> {
+        let matches = <Cli as CommandFactory>::command().get_matches();
+        let command = match matches.subcommand_name() {
+            Some(_) => Some(Command::from_arg_matches(&matches)?),
+            None => None,
+        };
+        let (config_file, file_keys) = config_layers(&matches)?;
+        let sources = sources(&matches, &file_keys);
+        let config_file = config_file.map(serde_json::from_value).transpose()?;
+        let mut config = Config::from_merged(matches, config_file);
+        config.load_secret_files()?;
+        Ok(Self {
+            config,
+            command,
+            sources,
+        })
+    }
+
+    /// Secrets passed as plain command line flags, visible to other users in the process list
+    pub fn plain_secret_flags(&self) -> Vec<&'static str> {
+        SECRETS
+            .into_iter()
+            .filter(|secret| matches!(self.sources.get(*secret), Some(ConfigSource::Flag(_))))
+            .collect()
+    }
+
+    /// Validates the config, reporting every problem at once along with the source of its value
+    pub fn check(&self) -> Result {
+        let Err(errors) = self.config.validate() else {
+            return Ok(());
+        };
+
+        let mut problems: Vec<String> = errors
+            .into_errors()
+            .into_iter()
+            .flat_map(|(field, kind)| match kind {
+                ValidationErrorsKind::Field(errors) => errors.into_iter().map(|err| (field.to_string(), err)).collect(),
+                _ => Vec::new(),
+            })
+            .map(|(field, err)| {
+                // Cross-field rules name their field as error code
+                let field = match field.as_str() {
+                    "__all__" => err.code.to_string(),
+                    _ => field,
+                };
+                let source = self.sources.get(&field).unwrap_or(&ConfigSource::Default);
+                let message = err.message.as_deref().unwrap_or(&err.code);
+                format!("  - {field} ({source}): {message}")
+            })
+            .collect();
+        problems.sort();
+        bail!("Invalid configuration:\n{}", problems.join("\n"))
+    }
+}
+
+/// Source of every setting, files only counting where clap fell back to its default
+fn sources(matches: &ArgMatches, file_keys: &HashMap<String, PathBuf>) -> HashMap<String, ConfigSource> {
+    <Config as CommandFactory>::command()
+        .get_arguments()
+        .map(|arg| {
+            let id = arg.get_id().to_string();
+            let source = match matches.value_source(&id) {
+                Some(ValueSource::CommandLine) => ConfigSource::Flag(arg.get_long().unwrap_or(id.as_str()).to_string()),
+                Some(ValueSource::EnvVariable) => ConfigSource::Env(
+                    arg.get_env()
+                        .map(|env| env.to_string_lossy().into_owned())
+                        .unwrap_or_default(),
+                ),
+                _ => match file_keys.get(&id) {
+                    Some(path) => ConfigSource::File(path.clone()),
+                    None => ConfigSource::Default,
+                },
+            };
+            (id, source)
+        })
+        .collect()
+}
+
+/// Merged config file and profile overlay, `None` when neither exists, with the file each key was read from
+fn config_layers(matches: &ArgMatches) -> Result<(Option<Value>, HashMap<String, PathBuf>)> {
+    let path = matches
+        .get_one::<PathBuf>("config_path")
+        .cloned()
+        .unwrap_or_else(|| CONFIG_PATH_DEFAULT.into());
+    let is_explicit = matches.value_source("config_path") != Some(ValueSource::DefaultValue);
+
+    let mut file_keys = HashMap::new();
+    let mut record_keys = |value: &Option<Value>, path: &Path| {
+        for key in value
+            .as_ref()
+            .and_then(Value::as_object)
+            .into_iter()
+            .flat_map(|object| object.keys())
+        {
+            file_keys.insert(key.clone(), path.to_path_buf());
+        }
+    };
+
+    let mut merged = read_config_file(&path, is_explicit)?;
+    record_keys(&merged, &path);
+    if let Some(profile) = matches.get_one::<String>("config_profile") {
+        let overlay_path = profile_path(&path, profile);
+        let overlay = read_config_file(&overlay_path, true)?;
+        record_keys(&overlay, &overlay_path);
+        merged = match (merged, overlay) {
+            (Some(mut base), Some(overlay)) => {
+                merge(&mut base, overlay);
+                Some(base)
+            }
+            (base, overlay) => base.or(overlay),
+        };
+    }
+    Ok((merged, file_keys))
+}
+
+/// Profile overlay of a config file, e.g. `config.prod.yaml` for `config.yaml`
+pub fn profile_path(path: &Path, profile: &str) -> PathBuf {
+    let stem = path.file_stem().unwrap_or_default().to_string_lossy();
+    match path.extension() {
+        Some(ext) => path.with_file_name(format!("{stem}.{profile}.{}", ext.to_string_lossy())),
+        None => path.with_file_name(format!("{stem}.{profile}")),
+    }
+}
+
+/// Reads a config file by its extension, a missing file being an error only when `is_required`
+fn read_config_file(path: &Path, is_required: bool) -> Result<Option<Value>> {
+    let content = match std::fs::read_to_string(path) {
+        Ok(content) => content,
+        Err(err) if err.kind() == std::io::ErrorKind::NotFound && !is_required => return Ok(None),
+        Err(err) => bail!("Failed to read config file {}: {err}", path.display()),
+    };
+
+    let extension = path.extension().and_then(|ext| ext.to_str()).unwrap_or_default();
+    let value = match extension {
+        "yaml" | "yml" => serde_yaml::from_str(&content).map_err(|err| eyre!(err)),
+        "toml" => toml::from_str(&content).map_err(|err| eyre!(err)),
+        "json" => serde_json::from_str(&content).map_err(|err| eyre!(err)),
+        _ => bail!("Unsupported config file format '{}', use yaml, toml or json", path.display()),
+    };
+    value
+        .map(Some)
+        .map_err(|err| eyre!("Invalid config file {}: {err}", path.display()))
+}
+
+/// Deep-merges `overlay` into `base`, overlay values winning
+fn merge(base: &mut Value, overlay: Value) {
+    match (base, overlay) {
+        (Value::Object(base), Value::Object(overlay)) => {
+            for (key, value) in overlay {
+                match base.get_mut(&key) {
+                    Some(existing) => merge(existing, value),
+                    None => {
+                        base.insert(key, value);
+                    }
+                }
+            }
+        }
+        (base, overlay) => *base = overlay,
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use clap::CommandFactory;
+    use serde_json::json;
+    use std::path::Path;
+    use uuid::Uuid;
+
+    use super::{Cli, config_layers, merge, profile_path};
+
+    #[test]
+    fn merge_given_profile_overlay_then_overlay_wins() {
+        // Given
+        let mut base = json!({ "server_port": 8080, "log_filter": "info", "server_cors": true });
+        let overlay = json!({ "server_port": 80, "server_cors": false });
+
+        // When
+        merge(&mut base, overlay);
+
+        // Then
+        assert_eq!(base, json!({ "server_port": 80, "log_filter": "info", "server_cors": false }));
+    }
+
+    #[test]
+    fn profile_path_given_extension_then_profile_inserted_before_extension() {
+        // Given
+        let path = Path::new("conf/config.yaml");
+
+        // When
+        let overlay = profile_path(path, "prod");
+
+        // Then
+        assert_eq!(overlay, Path::new("conf/config.prod.yaml"));
+    }
+
+    #[test]
+    fn config_layers_given_missing_selected_profile_then_error() {
+        // Given
+        let dir = std::env::temp_dir().join(format!("breezi-{}", Uuid::new_v4()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("config.yaml");
+        std::fs::write(&path, "server_port: 9000\n").unwrap();
+        let matches = <Cli as CommandFactory>::command()
+            .try_get_matches_from([
+                env!("CARGO_PKG_NAME"),
+                "--config-path",
+                path.to_str().unwrap(),
+                "--config-profile",
+                "prod",
+            ])
+            .unwrap();
+
+        // When
+        let result = config_layers(&matches);
+
+        // Then
+        let err = result.unwrap_err().to_string();
+        assert!(
+            err.starts_with(&format!(
+                "Failed to read config file {}",
+                dir.join("config.prod.yaml").display()
+            )),
+            "{err}"
+        );
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+}
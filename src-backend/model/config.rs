@@ -1,12 +1,29 @@
-use crate::Result;
+use crate::{Result, logic::is_dir_writable, model::Secret};
 use clap::*;
 use clap_config::ClapConfig;
-use color_eyre::eyre::bail;
-use std::{net::IpAddr, path::PathBuf};
+use color_eyre::eyre::{WrapErr, bail};
+use ipnet::IpNet;
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use std::{
+    fmt::Display,
+    net::IpAddr,
+    path::{Path, PathBuf},
+    str::FromStr,
+};
+use tracing_subscriber::EnvFilter;
+use validator::{Validate, ValidationError};
 
-const CONFIG_PATH_DEFAULT: &str = "./config.yaml";
+/// [`Secret`] settings, each also loadable from the file named by its `{name}_file` setting
+pub(crate) const SECRETS: [&str; 1] = ["admin_token"];
 
-#[derive(ClapConfig, Parser, Debug, Clone)]
+pub(crate) const CONFIG_PATH_DEFAULT: &str = "./config.yaml";
+
+#[derive(ClapConfig, Parser, Serialize, Validate, JsonSchema, Debug, Clone)]
+#[validate(schema(function = "validate_bindings_dir", skip_on_field_errors = false))]
+#[validate(schema(function = "validate_ports", skip_on_field_errors = false))]
+#[validate(schema(function = "validate_database_pool", skip_on_field_errors = false))]
+#[validate(schema(function = "validate_tls", skip_on_field_errors = false))]
 pub struct Config {
     /// Binding generation toggle
     #[arg(long, env, default_value_t = true)]
@@ -15,34 +32,465 @@ pub struct Config {
     #[arg(long, env, default_value = "./bindings")]
     pub bindings_dir: PathBuf,
 
-    /// Config file path
+    /// Config file path (`.yaml`, `.yml`, `.toml` or `.json`), optional unless set explicitly
     #[arg(short, long, env, default_value = CONFIG_PATH_DEFAULT)]
     pub config_path: PathBuf,
+    /// Config profile, overlaying `config.{profile}.{ext}` next to the config file (e.g. `config.prod.yaml`)
+    #[arg(long, env)]
+    pub config_profile: Option<String>,
 
     /// Database (sqlite) file path
     #[arg(short, long, env, default_value = "data/breezi.db")]
+    #[validate(custom(function = "validate_database"))]
     pub database: PathBuf,
+    /// SQLite journal mode, WAL lets readers proceed while a write is in progress
+    #[arg(long, env, value_enum, default_value_t = JournalMode::Wal)]
+    pub database_journal_mode: JournalMode,
+    /// SQLite synchronous level, `normal` is durable across application crashes in WAL mode
+    #[arg(long, env, value_enum, default_value_t = Synchronous::Normal)]
+    pub database_synchronous: Synchronous,
+    /// Milliseconds a connection waits for a lock held by another before failing as busy
+    #[arg(long, env, default_value_t = 5000)]
+    pub database_busy_timeout_ms: u64,
+    /// SQLite foreign key enforcement toggle
+    #[arg(long, env, default_value_t = true)]
+    pub database_foreign_keys: bool,
+    /// SQLite page cache size of each connection in KiB
+    #[arg(long, env, default_value_t = 8192)]
+    pub database_cache_size_kib: u32,
+    /// Connections the pool keeps open while idle
+    #[arg(long, env, default_value_t = 1)]
+    pub database_pool_min: u32,
+    /// Connections the pool opens at most
+    #[arg(long, env, default_value_t = 10)]
+    #[validate(range(min = 1, message = "must allow at least one connection"))]
+    pub database_pool_max: u32,
+    /// Seconds to wait for a pooled connection before failing
+    #[arg(long, env, default_value_t = 30)]
+    pub database_acquire_timeout: u64,
+    /// Seconds before an idle connection above `database_pool_min` is closed, 0 keeps them open
+    #[arg(long, env, default_value_t = 600)]
+    pub database_idle_timeout: u64,
+    /// Directory of fixture sets loaded by `seed`, one subdirectory per set (e.g. `fixtures/dev`)
+    #[arg(long, env, default_value = "./fixtures")]
+    pub fixtures_dir: PathBuf,
 
+    /// TCP listener on `server_host:server_port` toggle, disable when only using unix or inherited sockets
+    #[arg(long, env, default_value_t = true)]
+    pub server_tcp: bool,
     /// Server host binding address.
     #[arg(long, env, default_value = "127.0.0.1")]
     pub server_host: IpAddr,
     /// Server host binding port
     #[arg(short = 'p', long, env, default_value_t = 8080)]
     pub server_port: u16,
+    /// Unix domain socket path to listen on besides TCP (e.g. for a reverse proxy on the same host)
+    #[arg(long, env)]
+    pub server_unix_socket: Option<PathBuf>,
+    /// Octal file mode of the unix domain socket
+    #[arg(long, env, default_value = "660")]
+    #[validate(custom(function = "validate_socket_mode"))]
+    pub server_unix_socket_mode: String,
+    /// PEM certificate chain of the TLS terminator in front of the server, checked by `doctor`, set together with `server_tls_key`
+    #[arg(long, env)]
+    pub server_tls_cert: Option<PathBuf>,
+    /// PEM private key of `server_tls_cert`
+    #[arg(long, env)]
+    pub server_tls_key: Option<PathBuf>,
+    /// Proxy networks (CIDR, e.g. `10.0.0.0/8,::1/128`) whose `Forwarded` and `X-Forwarded-*` headers are trusted.
+    /// Unix socket peers count as trusted once any network is set.
+    #[arg(long, env, value_delimiter = ',')]
+    #[schemars(with = "Vec<String>")]
+    pub server_trusted_proxies: Vec<IpNet>,
+    /// Path prefix every route is served under (e.g. `/breezi/`)
+    #[arg(long, env, default_value = "/")]
+    #[validate(custom(function = "validate_base_path"))]
+    pub base_path: String,
+    /// Maintenance mode at startup, changeable at runtime through `admin.set_maintenance`
+    #[arg(long, env, value_enum, default_value_t = MaintenanceMode::Off)]
+    pub maintenance: MaintenanceMode,
     /// Server host CORS (Cross-origin resource sharing) toggle
     #[arg(long, env, default_value_t = true)]
     pub server_cors: bool,
+    /// Vite dev server URL (e.g. `http://localhost:3000`) all non-`/rpc` traffic is proxied to instead of the embedded SPA
+    #[arg(long, env)]
+    #[validate(url(message = "must be a URL such as http://localhost:3000"))]
+    pub server_dev_proxy: Option<String>,
+    /// Bearer token authorizing admin RPC calls, admin RPCs are disabled when unset
+    #[arg(long, env)]
+    pub admin_token: Option<Secret>,
+    /// File holding `admin_token` (e.g. a Docker or Kubernetes secret), instead of passing it directly
+    #[arg(long, env)]
+    pub admin_token_file: Option<PathBuf>,
+
+    /// Prometheus metrics endpoint (`/metrics`) toggle, unauthenticated so prefer a `metrics_port` off the public listener
+    #[arg(long, env, default_value_t = false)]
+    pub metrics_enabled: bool,
+    /// Prometheus metrics port, serves `/metrics` on its own listener instead of the server port when set
+    #[arg(long, env)]
+    pub metrics_port: Option<u16>,
+
+    /// Client key rate limits are tracked by, `user` and `api_key` fall back to the client IP without a verified API key
+    #[arg(long, env, value_enum, default_value_t = RateLimitKey::Ip)]
+    pub rate_limit_key: RateLimitKey,
+    /// HTTP requests allowed per client per minute across the router, 0 disables the limit
+    #[arg(long, env, default_value_t = 600)]
+    pub rate_limit_http: u32,
+    /// RPC calls allowed per client per minute for each handler, 0 disables the limit
+    #[arg(long, env, default_value_t = 60)]
+    pub rate_limit_rpc: u32,
+    /// Per-handler overrides of `rate_limit_rpc` as `handler=calls_per_minute` (e.g. `register=5,admin.set_log_filter=10`)
+    #[arg(long, env, value_delimiter = ',')]
+    #[schemars(with = "Vec<String>")]
+    pub rate_limit_handlers: Vec<HandlerOverride<u32>>,
+
+    /// Maximum request body size in bytes
+    #[arg(long, env, default_value_t = 1024 * 1024)]
+    pub body_limit: usize,
+    /// Per-handler overrides of `body_limit` as `handler=bytes` (e.g. `register=4096`)
+    #[arg(long, env, value_delimiter = ',')]
+    #[schemars(with = "Vec<String>")]
+    pub body_limit_handlers: Vec<HandlerOverride<usize>>,
+    /// Seconds before a request is answered with a gateway timeout, 0 disables the timeout
+    #[arg(long, env, default_value_t = 30)]
+    pub request_timeout: u64,
+    /// Per-handler timeouts in seconds as `handler=seconds` (e.g. `register=5`)
+    #[arg(long, env, value_delimiter = ',')]
+    #[schemars(with = "Vec<String>")]
+    pub request_timeout_handlers: Vec<HandlerOverride<u64>>,
+    /// Requests handled concurrently before new ones are shed as unavailable, 0 disables the limit
+    #[arg(long, env, default_value_t = 1024)]
+    pub concurrency_limit: usize,
+
+    /// Log output format
+    #[arg(long, env, value_enum, default_value_t = LogFormat::Full)]
+    pub log_format: LogFormat,
+    /// Log filter directives in `EnvFilter` syntax (e.g. `info,breezi=debug,sqlx=warn`)
+    #[arg(long, env, default_value = "info")]
+    #[validate(custom(function = "validate_log_filter"))]
+    pub log_filter: String,
+    /// Directory for rolling log files, file logging is off when unset
+    #[arg(long, env)]
+    pub log_dir: Option<PathBuf>,
+    /// Log file rotation period
+    #[arg(long, env, value_enum, default_value_t = LogRotation::Daily)]
+    pub log_rotation: LogRotation,
+    /// Number of rotated log files to retain
+    #[arg(long, env, default_value_t = 7)]
+    #[validate(range(min = 1, message = "must keep at least one log file"))]
+    pub log_max_files: usize,
+
+    /// OpenTelemetry OTLP/HTTP traces endpoint (e.g. `http://localhost:4318/v1/traces`), exporting is off when unset
+    #[arg(long, env)]
+    #[validate(url(message = "must be a URL such as http://localhost:4318/v1/traces"))]
+    pub otel_endpoint: Option<String>,
+    /// OpenTelemetry service name reported with exported spans
+    #[arg(long, env, default_value = "breezi")]
+    pub otel_service_name: String,
+}
+
+#[derive(ValueEnum, Serialize, Deserialize, JsonSchema, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum JournalMode {
+    Delete,
+    Truncate,
+    Persist,
+    Memory,
+    Wal,
+    Off,
+}
+
+#[derive(ValueEnum, Serialize, Deserialize, JsonSchema, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum Synchronous {
+    Off,
+    Normal,
+    Full,
+    Extra,
+}
+
+#[derive(ValueEnum, Serialize, Deserialize, JsonSchema, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum LogFormat {
+    Full,
+    Pretty,
+    Compact,
+    Json,
+}
+
+#[derive(ValueEnum, Serialize, Deserialize, JsonSchema, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum LogRotation {
+    Minutely,
+    Hourly,
+    Daily,
+    Never,
+}
+
+/// Maintenance mode, admin RPCs staying available in every mode so it can be lifted
+#[derive(ValueEnum, Serialize, Deserialize, JsonSchema, ts_rs::TS, Debug, Default, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum MaintenanceMode {
+    #[default]
+    Off,
+    /// Mutations are rejected while queries keep working
+    ReadOnly,
+    /// The maintenance page replaces the SPA and every RPC is rejected
+    Full,
+}
+
+#[derive(ValueEnum, Serialize, Deserialize, JsonSchema, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum RateLimitKey {
+    Ip,
+    User,
+    ApiKey,
+}
+
+/// Setting for a single RPC handler (e.g. `admin.set_log_filter`), parsed from `handler=value`
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+#[serde(
+    try_from = "String",
+    into = "String",
+    bound(deserialize = "T: FromStr, T::Err: Display", serialize = "T: Display + Clone")
+)]
+pub struct HandlerOverride<T> {
+    pub handler: String,
+    pub value: T,
+}
+
+impl<T: FromStr<Err: Display>> FromStr for HandlerOverride<T> {
+    type Err = String;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        let (handler, setting) = value
+            .split_once('=')
+            .ok_or_else(|| format!("expected `handler=value`, got '{value}'"))?;
+        let setting = setting
+            .trim()
+            .parse()
+            .map_err(|err| format!("invalid value for handler '{handler}': {err}"))?;
+        Ok(Self {
+            handler: handler.trim().to_string(),
+            value: setting,
+        })
+    }
+}
+
+impl<T: FromStr<Err: Display>> TryFrom<String> for HandlerOverride<T> {
+    type Error = String;
+
+    fn try_from(value: String) -> Result<Self, Self::Error> {
+        value.parse()
+    }
+}
+
+impl<T: Display> From<HandlerOverride<T>> for String {
+    fn from(value: HandlerOverride<T>) -> Self {
+        format!("{}={}", value.handler, value.value)
+    }
+}
+
+/// Error of a cross-field rule, coded with the field it is reported against
+fn invalid(field: &'static str, message: String) -> ValidationError {
+    ValidationError::new(field).with_message(message.into())
+}
+
+/// Nearest existing ancestor of a path, i.e. the directory it would be created in
+fn existing_ancestor(path: &Path) -> Option<&Path> {
+    path.ancestors()
+        .map(|ancestor| match ancestor.as_os_str().is_empty() {
+            true => Path::new("."),
+            false => ancestor,
+        })
+        .find(|ancestor| ancestor.exists())
+}
+
+fn validate_writable_dir(dir: &Path) -> Result<(), String> {
+    match existing_ancestor(dir) {
+        Some(ancestor) if !ancestor.is_dir() => Err(format!("{} is not a directory", ancestor.display())),
+        Some(ancestor) if !is_dir_writable(ancestor) => Err(format!("{} is not writable", ancestor.display())),
+        Some(_) => Ok(()),
+        None => Err(format!("no parent of {} exists", dir.display())),
+    }
+}
+
+fn validate_bindings_dir(config: &Config) -> Result<(), ValidationError> {
+    if !config.bindings_generate {
+        return Ok(());
+    }
+    validate_writable_dir(&config.bindings_dir)
+        .map_err(|reason| invalid("bindings_dir", format!("bindings can't be generated, {reason}")))
+}
+
+fn validate_ports(config: &Config) -> Result<(), ValidationError> {
+    if config.server_tcp && config.server_port < 1024 {
+        return Err(invalid(
+            "server_port",
+            format!(
+                "port {} is privileged, use a port >= 1024 behind a reverse proxy or systemd socket activation",
+                config.server_port
+            ),
+        ));
+    }
+    if config
+        .metrics_port
+        .is_some_and(|port| config.server_tcp && port == config.server_port)
+    {
+        return Err(invalid(
+            "metrics_port",
+            format!("port {} is already used by server_port", config.server_port),
+        ));
+    }
+    Ok(())
+}
+
+fn validate_database_pool(config: &Config) -> Result<(), ValidationError> {
+    if config.database_pool_min > config.database_pool_max {
+        return Err(invalid(
+            "database_pool_min",
+            format!(
+                "{} exceeds database_pool_max of {}",
+                config.database_pool_min, config.database_pool_max
+            ),
+        ));
+    }
+    Ok(())
+}
+
+fn validate_tls(config: &Config) -> Result<(), ValidationError> {
+    match (&config.server_tls_cert, &config.server_tls_key) {
+        (Some(_), None) => Err(invalid("server_tls_key", "must be set along with server_tls_cert".into())),
+        (None, Some(_)) => Err(invalid("server_tls_cert", "must be set along with server_tls_key".into())),
+        _ => Ok(()),
+    }
+}
+
+fn validate_database(database: &Path) -> Result<(), ValidationError> {
+    let dir = database.parent().unwrap_or(Path::new("."));
+    validate_writable_dir(dir)
+        .map_err(|reason| ValidationError::new("database").with_message(format!("database can't be created, {reason}").into()))
+}
+
+fn validate_socket_mode(mode: &str) -> Result<(), ValidationError> {
+    u32::from_str_radix(mode, 8)
+        .ok()
+        .filter(|mode| *mode <= 0o777)
+        .map(|_| ())
+        .ok_or_else(|| {
+            ValidationError::new("octal").with_message(format!("'{mode}' is not an octal file mode such as 660").into())
+        })
+}
+
+fn validate_base_path(path: &str) -> Result<(), ValidationError> {
+    match path.contains(|c: char| c.is_whitespace() || c == '?' || c == '#') {
+        true => Err(ValidationError::new("base_path").with_message("must be a plain path such as /breezi/".into())),
+        false => Ok(()),
+    }
+}
+
+fn validate_log_filter(filter: &str) -> Result<(), ValidationError> {
+    EnvFilter::try_new(filter)
+        .map(|_| ())
+        .map_err(|err| ValidationError::new("log_filter").with_message(format!("invalid filter directives: {err}").into()))
+}
+
+/// Looks up the override of a handler
+pub fn handler_override<T: Copy>(overrides: &[HandlerOverride<T>], handler: &str) -> Option<T> {
+    overrides.iter().find(|o| o.handler == handler).map(|o| o.value)
 }
 
 impl Config {
-    pub fn parse() -> Result<Self> {
-        let config_file = match std::fs::read_to_string(CONFIG_PATH_DEFAULT) {
-            Ok(config_str) => Some(serde_yaml::from_str(&config_str)?),
-            Err(err) if err.raw_os_error().is_some_and(|v| v == 2) => None,
-            Err(err) => bail!(err),
-        };
-        let matches = <Config as CommandFactory>::command().get_matches();
-        let config = Config::from_merged(matches, config_file);
-        Ok(config)
+    /// Built-in defaults, ignoring environment variables and config files
+    pub fn defaults() -> Result<Self> {
+        let command = <Self as CommandFactory>::command().mut_args(|arg| arg.env(None::<&str>));
+        let matches = command.try_get_matches_from([env!("CARGO_PKG_NAME")])?;
+        Ok(Self::from_arg_matches(&matches)?)
+    }
+
+    /// `base_path` with leading and trailing slashes, e.g. `/breezi/`
+    pub fn base_path(&self) -> String {
+        match self.base_path.trim_matches('/') {
+            "" => "/".into(),
+            path => format!("/{path}/"),
+        }
+    }
+
+    /// `server_tls_cert` and `server_tls_key`, when both are set
+    pub fn tls_files(&self) -> Option<(&Path, &Path)> {
+        self.server_tls_cert.as_deref().zip(self.server_tls_key.as_deref())
+    }
+
+    /// Reads secrets from their `*_file` settings
+    pub fn load_secret_files(&mut self) -> Result {
+        if let Some(path) = &self.admin_token_file {
+            if self.admin_token.is_some() {
+                bail!("Set either admin_token or admin_token_file, not both");
+            }
+            self.admin_token = Some(read_secret(path)?);
+        }
+        Ok(())
+    }
+}
+
+/// Reads a secret file, trimming the trailing newline such files usually end with
+fn read_secret(path: &Path) -> Result<Secret> {
+    let secret = std::fs::read_to_string(path).wrap_err_with(|| format!("Failed to read secret file {}", path.display()))?;
+    Ok(Secret::from(secret.trim_end_matches(['\r', '\n']).to_string()))
+}
+
+#[cfg(test)]
+mod test {
+    use clap::Parser;
+    use uuid::Uuid;
+    use validator::Validate;
+
+    use super::{Config, validate_writable_dir};
+    use crate::logic::is_dir_writable;
+
+    #[test]
+    fn validate_given_several_problems_then_all_reported() {
+        // Given
+        let config = Config::parse_from([
+            env!("CARGO_PKG_NAME"),
+            "--server-port",
+            "80",
+            "--server-unix-socket-mode",
+            "999",
+            "--log-filter",
+            "breezi=loud",
+        ]);
+
+        // When
+        let errors = config.validate().unwrap_err();
+
+        // Then
+        let errors = errors.errors();
+        assert!(errors.contains_key("server_unix_socket_mode"));
+        assert!(errors.contains_key("log_filter"));
+        assert!(errors.contains_key("__all__")); // Privileged `server_port`
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn validate_writable_dir_given_read_only_dir_then_not_writable() {
+        use std::os::unix::fs::PermissionsExt;
+
+        // Given
+        let dir = std::env::temp_dir().join(format!("breezi-{}", Uuid::new_v4()));
+        std::fs::create_dir_all(&dir).unwrap();
+        std::fs::set_permissions(&dir, std::fs::Permissions::from_mode(0o555)).unwrap();
+
+        // When
+        let result = validate_writable_dir(&dir.join("bindings"));
+
+        // Then
+        // Root writes regardless of permissions, which the probe rightly reports
+        if !is_dir_writable(&dir) {
+            assert_eq!(result, Err(format!("{} is not writable", dir.display())));
+        }
+        std::fs::set_permissions(&dir, std::fs::Permissions::from_mode(0o755)).unwrap();
+        assert_eq!(validate_writable_dir(&dir.join("bindings")), Ok(()));
+        std::fs::remove_dir_all(dir).unwrap();
     }
 }
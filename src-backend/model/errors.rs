@@ -1,16 +1,24 @@
 use std::collections::HashMap;
 
+use axum::{
+    Json,
+    http::{HeaderValue, StatusCode, header::RETRY_AFTER},
+    response::{IntoResponse, Response},
+};
 use chrono::{DateTime, Utc};
 use serde::{Deserialize, Serialize};
+use tracing::error;
 use validator::ValidationErrors;
 
-use crate::logic::Invalidation;
+use crate::logic::{Invalidation, current_request_id};
 
 #[derive(Debug, Clone, Eq, PartialEq, Serialize, Deserialize, ts_rs::TS)]
 pub struct ErrorResponse {
     pub reason: ErrorReason,
     pub message: String,
     pub timestamp: DateTime<Utc>,
+    /// `X-Request-Id` of the failed request, for correlating with server logs
+    pub request_id: Option<String>,
 }
 
 impl ErrorResponse {
@@ -21,12 +29,24 @@ impl ErrorResponse {
             reason,
             message,
             timestamp: Utc::now(),
+            request_id: current_request_id(),
         }
     }
 
     pub fn internal() -> ErrorResponse {
         Self::default()
     }
+
+    /// Logs the full error server-side under the request ID, while the client only gets the generic message
+    pub fn internal_from(err: impl std::fmt::Debug) -> ErrorResponse {
+        let response = Self::internal();
+        error!(
+            request_id = response.request_id.as_deref().unwrap_or("-"),
+            "{}: {err:?}",
+            Self::INTERNAL_MESSAGE
+        );
+        response
+    }
 }
 
 impl Default for ErrorResponse {
@@ -43,12 +63,62 @@ pub enum ErrorReason {
     Forbidden,
     NotFound,
     Conflict,
+    TooManyRequests {
+        /// Seconds until the caller may retry
+        retry_after_secs: u32,
+    },
     #[default]
     Internal,
     ServiceUnavailable,
     GatewayTimeout,
 }
 
+impl ErrorReason {
+    /// Variant name without its payload, e.g. for use as a metrics label
+    pub fn name(&self) -> &'static str {
+        match self {
+            ErrorReason::BadRequest => "BadRequest",
+            ErrorReason::Invalid(_) => "Invalid",
+            ErrorReason::Unauthorized => "Unauthorized",
+            ErrorReason::Forbidden => "Forbidden",
+            ErrorReason::NotFound => "NotFound",
+            ErrorReason::Conflict => "Conflict",
+            ErrorReason::TooManyRequests { .. } => "TooManyRequests",
+            ErrorReason::Internal => "Internal",
+            ErrorReason::ServiceUnavailable => "ServiceUnavailable",
+            ErrorReason::GatewayTimeout => "GatewayTimeout",
+        }
+    }
+
+    /// HTTP status equivalent of the reason
+    pub fn status_code(&self) -> StatusCode {
+        match self {
+            ErrorReason::BadRequest | ErrorReason::Invalid(_) => StatusCode::BAD_REQUEST,
+            ErrorReason::Unauthorized => StatusCode::UNAUTHORIZED,
+            ErrorReason::Forbidden => StatusCode::FORBIDDEN,
+            ErrorReason::NotFound => StatusCode::NOT_FOUND,
+            ErrorReason::Conflict => StatusCode::CONFLICT,
+            ErrorReason::TooManyRequests { .. } => StatusCode::TOO_MANY_REQUESTS,
+            ErrorReason::Internal => StatusCode::INTERNAL_SERVER_ERROR,
+            ErrorReason::ServiceUnavailable => StatusCode::SERVICE_UNAVAILABLE,
+            ErrorReason::GatewayTimeout => StatusCode::GATEWAY_TIMEOUT,
+        }
+    }
+}
+
+/// For errors raised outside qubit handlers, e.g. by router middleware
+impl IntoResponse for ErrorResponse {
+    fn into_response(self) -> Response {
+        let mut response = (self.reason.status_code(), Json(&self)).into_response();
+        if let ErrorReason::TooManyRequests { retry_after_secs } = self.reason {
+            response
+                .headers_mut()
+                .insert(RETRY_AFTER, HeaderValue::from(retry_after_secs));
+        }
+        response
+    }
+}
+
 impl From<sqlx::Error> for ErrorResponse {
     fn from(value: sqlx::Error) -> Self {
         match value {
@@ -81,7 +151,7 @@ impl From<sqlx::Error> for ErrorResponse {
                 ErrorReason::GatewayTimeout,
                 "Database pool timed out (504 Gateway Timeout)".to_string(),
             ),
-            _ => ErrorResponse::default(),
+            err => ErrorResponse::internal_from(err),
         }
     }
 }
@@ -148,7 +218,7 @@ macro_rules! impl_from_report_for_error_response {
                         return value.downcast::<$err_ty>().unwrap().into();
                     }
                 )*
-                Self::internal()
+                Self::internal_from(value)
             }
         }
     };
@@ -1,7 +1,13 @@
+mod api_key;
+mod cli;
 mod config;
 mod errors;
+mod secret;
 mod user;
 
+pub use api_key::*;
+pub use cli::*;
 pub use config::*;
 pub use errors::*;
+pub use secret::*;
 pub use user::*;
@@ -0,0 +1,81 @@
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize, Serializer};
+use std::{convert::Infallible, fmt, str::FromStr};
+use subtle::ConstantTimeEq;
+
+const REDACTED: &str = "<redacted>";
+
+/// Sensitive setting (token, password, key), redacted in `Debug` and serialized output.
+/// Only [`Secret::expose`] reveals it, so it can't end up in logs or `config show` by accident.
+#[derive(Clone, PartialEq, Eq, Deserialize, JsonSchema)]
+#[serde(transparent)]
+pub struct Secret(String);
+
+impl Secret {
+    pub fn expose(&self) -> &str {
+        &self.0
+    }
+
+    /// Compares in constant time, so response timing doesn't reveal how much of a guess matched
+    pub fn matches(&self, given: &str) -> bool {
+        self.0.as_bytes().ct_eq(given.as_bytes()).into()
+    }
+}
+
+impl fmt::Debug for Secret {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(REDACTED)
+    }
+}
+
+impl Serialize for Secret {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(REDACTED)
+    }
+}
+
+impl FromStr for Secret {
+    type Err = Infallible;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        Ok(Self(value.to_string()))
+    }
+}
+
+impl From<String> for Secret {
+    fn from(value: String) -> Self {
+        Self(value)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::Secret;
+
+    #[test]
+    fn secret_given_debug_or_serialized_then_redacted() {
+        // Given
+        let secret: Secret = "hunter2".parse().unwrap();
+
+        // When
+        let debug = format!("{:?}", Some(&secret));
+        let json = serde_json::to_string(&secret).unwrap();
+
+        // Then
+        assert_eq!(debug, "Some(<redacted>)");
+        assert_eq!(json, "\"<redacted>\"");
+        assert_eq!(secret.expose(), "hunter2");
+    }
+
+    #[test]
+    fn matches_given_prefix_or_equal_then_only_equal_matches() {
+        // Given
+        let secret: Secret = "hunter2".parse().unwrap();
+
+        // When
+        let matches = ["hunter2", "hunter", "hunter22", ""].map(|given| secret.matches(given));
+
+        // Then
+        assert_eq!(matches, [true, false, false, false]);
+    }
+}
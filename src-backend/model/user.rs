@@ -2,12 +2,15 @@ use crate::logic::{REGEX_USERNAME, REGEX_UUID};
 use schemars::JsonSchema;
 use serde::{Deserialize, Serialize};
 use sqlx::{Executor, Sqlite};
+use std::fmt::Display;
+use tracing::instrument;
 use uuid::Uuid;
 use validator::Validate;
 
 #[derive(restructed::Models)] // must be separate
 #[derive(Debug, Clone, Serialize, Deserialize, sqlx::FromRow, ts_rs::TS, PartialEq, Eq, Validate, JsonSchema)]
 #[view(UserRegistration, fields(username, password, email), attributes_with = "all")]
+#[view(UserPassword, fields(password), attributes_with = "all")]
 pub struct UserAll {
     #[validate(regex(path = *REGEX_UUID, code = "uuid"))]
     pub id: String,
@@ -20,6 +23,7 @@ pub struct UserAll {
 }
 
 impl UserRegistration {
+    #[instrument(name = "sqlx.query", skip_all, fields(db.system = "sqlite", db.operation = "INSERT", db.table = "user"))]
     pub async fn insert(&self, conn: impl Executor<'_, Database = Sqlite>) -> crate::Result<String> {
         let id = Uuid::new_v4().to_string();
         sqlx::query!(
@@ -36,3 +40,82 @@ impl UserRegistration {
         Ok(id)
     }
 }
+
+/// Role of a user, stored as lowercase text
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize, sqlx::Type, clap::ValueEnum)]
+#[sqlx(rename_all = "lowercase")]
+#[serde(rename_all = "lowercase")]
+pub enum UserRole {
+    #[default]
+    User,
+    Admin,
+}
+
+impl Display for UserRole {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            UserRole::User => f.write_str("user"),
+            UserRole::Admin => f.write_str("admin"),
+        }
+    }
+}
+
+/// User as listed to operators, without the password
+#[derive(Debug, Clone, PartialEq, Eq, sqlx::FromRow)]
+pub struct UserListing {
+    pub id: String,
+    pub username: String,
+    pub email: String,
+    pub role: UserRole,
+}
+
+impl UserListing {
+    #[instrument(name = "sqlx.query", skip_all, fields(db.system = "sqlite", db.operation = "SELECT", db.table = "user"))]
+    pub async fn all(conn: impl Executor<'_, Database = Sqlite>) -> crate::Result<Vec<Self>> {
+        Ok(sqlx::query_as!(
+            UserListing,
+            r#"SELECT id, username, email, role AS "role: UserRole" FROM user ORDER BY username"#
+        )
+        .fetch_all(conn)
+        .await?)
+    }
+}
+
+impl UserAll {
+    /// Whether any user is an admin
+    #[instrument(name = "sqlx.query", skip_all, fields(db.system = "sqlite", db.operation = "SELECT", db.table = "user"))]
+    pub async fn admin_exists(conn: impl Executor<'_, Database = Sqlite>) -> crate::Result<bool> {
+        let found = sqlx::query_scalar!("SELECT 1 FROM user WHERE role = 'admin' LIMIT 1")
+            .fetch_optional(conn)
+            .await?;
+        Ok(found.is_some())
+    }
+
+    /// Whether a user with this username exists
+    #[instrument(name = "sqlx.query", skip_all, fields(db.system = "sqlite", db.operation = "SELECT", db.table = "user"))]
+    pub async fn exists(conn: impl Executor<'_, Database = Sqlite>, username: &str) -> crate::Result<bool> {
+        let found = sqlx::query_scalar!("SELECT 1 FROM user WHERE username = $1", username)
+            .fetch_optional(conn)
+            .await?;
+        Ok(found.is_some())
+    }
+
+    /// Sets the password of a user, returning whether the user exists
+    #[instrument(name = "sqlx.query", skip_all, fields(db.system = "sqlite", db.operation = "UPDATE", db.table = "user"))]
+    pub async fn set_password(conn: impl Executor<'_, Database = Sqlite>, username: &str, password: &str) -> crate::Result<bool> {
+        let result = sqlx::query!("UPDATE user SET password = $1 WHERE username = $2", password, username)
+            .execute(conn)
+            .await?;
+        Ok(result.rows_affected() > 0)
+    }
+
+    /// Sets the role of a user, returning whether the user exists
+    #[instrument(name = "sqlx.query", skip_all, fields(db.system = "sqlite", db.operation = "UPDATE", db.table = "user"))]
+    pub async fn set_role(conn: impl Executor<'_, Database = Sqlite>, username: &str, role: UserRole) -> crate::Result<bool> {
+        let role = role.to_string();
+        let result = sqlx::query!("UPDATE user SET role = $1 WHERE username = $2", role, username)
+            .execute(conn)
+            .await?;
+        Ok(result.rows_affected() > 0)
+    }
+}
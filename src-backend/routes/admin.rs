@@ -0,0 +1,32 @@
+use qubit::{Router, handler};
+use tracing::info;
+
+use crate::logic::track_mutation;
+use crate::model::{ErrorReason, ErrorResponse, MaintenanceMode};
+use crate::routes::{Ctx, RequestCtx};
+
+#[handler(mutation)]
+async fn set_log_filter(ctx: RequestCtx, filter: String) -> crate::Result<(), ErrorResponse> {
+    track_mutation(&ctx, "admin.set_log_filter", async {
+        ctx.authorize_admin()?;
+        crate::logic::set_log_filter(&filter).map_err(|err| ErrorResponse::new(ErrorReason::BadRequest, err.to_string()))?;
+        info!("Log filter changed to '{filter}'");
+        Ok(())
+    })
+    .await
+}
+
+#[handler(mutation)]
+async fn set_maintenance(ctx: RequestCtx, mode: MaintenanceMode) -> crate::Result<(), ErrorResponse> {
+    track_mutation(&ctx, "admin.set_maintenance", async {
+        ctx.authorize_admin()?;
+        ctx.maintenance.set(mode);
+        info!("Maintenance mode changed to {mode:?}");
+        Ok(())
+    })
+    .await
+}
+
+pub fn router() -> Router<Ctx> {
+    qubit::Router::<Ctx>::new().handler(set_log_filter).handler(set_maintenance)
+}
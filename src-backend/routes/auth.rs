@@ -1,21 +1,50 @@
 use qubit::Router;
 use qubit::handler;
+use sqlx::Connection as _;
 use tracing::info;
 use validator::Validate;
 
+use crate::logic::track_mutation;
 use crate::model::ErrorResponse;
-use crate::model::UserRegistration;
-use crate::routes::Ctx;
+use crate::model::{ErrorReason, UserAll, UserRegistration, UserRole};
+use crate::routes::{Ctx, RequestCtx};
 
 #[handler(mutation)]
-async fn register(ctx: Ctx, user: UserRegistration) -> crate::Result<String, ErrorResponse> {
-    info!("Registering");
-    user.validate()?;
-    Ok(user.insert(&ctx.pool).await?)
+async fn register(ctx: RequestCtx, user: UserRegistration) -> crate::Result<String, ErrorResponse> {
+    track_mutation(&ctx, "register", async {
+        info!("Registering");
+        user.validate()?;
+        Ok(user.insert(&mut *ctx.acquire().await?).await?)
+    })
+    .await
+}
+
+/// Creates the first admin with the one-time token logged at startup, disabled once an admin exists
+#[handler(mutation)]
+async fn setup(ctx: RequestCtx, token: String, user: UserRegistration) -> crate::Result<String, ErrorResponse> {
+    track_mutation(&ctx, "setup", async {
+        let mut setup_token = ctx.setup.redeem(&token).await?;
+        user.validate()?;
+
+        let mut conn = ctx.acquire().await?;
+        let mut tx = conn.begin().await?;
+        if UserAll::admin_exists(&mut *tx).await? {
+            setup_token.take();
+            return Err(ErrorResponse::new(ErrorReason::Forbidden, "Setup is already done".into()));
+        }
+        let id = user.insert(&mut *tx).await?;
+        UserAll::set_role(&mut *tx, &user.username, UserRole::Admin).await?;
+        tx.commit().await?;
+
+        setup_token.take();
+        info!("Setup created the first admin {}", user.username);
+        Ok(id)
+    })
+    .await
 }
 
 pub fn router() -> Router<Ctx> {
-    qubit::Router::<Ctx>::new().handler(register)
+    qubit::Router::<Ctx>::new().handler(register).handler(setup)
 }
 
 #[cfg(test)]
@@ -44,7 +73,7 @@ mod test {
         let id = user_expected.insert(&pool).await.expect("user should insert");
 
         // Then
-        let user_actual = sqlx::query_as!(UserAll, r#"SELECT * FROM user WHERE id = $1"#, id)
+        let user_actual = sqlx::query_as!(UserAll, r#"SELECT id, username, password, email FROM user WHERE id = $1"#, id)
             .fetch_one(&pool)
             .await
             .unwrap();
@@ -0,0 +1,111 @@
+use crate::model::{ErrorReason, ErrorResponse};
+use axum::{
+    body::Body,
+    extract::{Request, State},
+    http::{
+        HeaderValue, StatusCode, Uri,
+        header::{HOST, UPGRADE},
+        uri::PathAndQuery,
+    },
+    response::{IntoResponse, Response},
+};
+use color_eyre::eyre::eyre;
+use hyper_util::{
+    client::legacy::{Client, connect::HttpConnector},
+    rt::{TokioExecutor, TokioIo},
+};
+use std::sync::Arc;
+use tracing::{debug, warn};
+
+/// Reverse proxy to the Vite dev server, making the SPA and `/rpc` same-origin during development
+#[derive(Debug, Clone)]
+pub struct DevProxy {
+    upstream: Uri,
+    client: Client<HttpConnector, Body>,
+}
+
+impl DevProxy {
+    pub fn new(upstream: &str) -> crate::Result<Arc<Self>> {
+        let upstream: Uri = upstream.parse()?;
+        if upstream.scheme_str() != Some("http") || upstream.authority().is_none() {
+            return Err(eyre!("Dev proxy upstream '{upstream}' must be an absolute http:// URL"));
+        }
+        let client = Client::builder(TokioExecutor::new()).build_http();
+        Ok(Arc::new(Self { upstream, client }))
+    }
+
+    /// Upstream URI of a proxied request, keeping its path and query
+    fn upstream_uri(&self, uri: &Uri) -> crate::Result<Uri> {
+        let mut parts = self.upstream.clone().into_parts();
+        parts.path_and_query = uri.path_and_query().cloned().or(Some(PathAndQuery::from_static("/")));
+        Ok(Uri::from_parts(parts)?)
+    }
+}
+
+/// Fallback handler forwarding a request to the dev server, tunnelling WebSocket upgrades (Vite HMR)
+pub async fn proxy(State(proxy): State<Arc<DevProxy>>, mut req: Request) -> Response {
+    let uri = match proxy.upstream_uri(req.uri()) {
+        Ok(uri) => uri,
+        Err(err) => return ErrorResponse::new(ErrorReason::BadRequest, err.to_string()).into_response(),
+    };
+    debug!("Proxying {} to {uri}", req.uri());
+
+    let client_upgrade = req.headers().contains_key(UPGRADE).then(|| hyper::upgrade::on(&mut req));
+    *req.uri_mut() = uri;
+    if let Some(Ok(host)) = proxy
+        .upstream
+        .authority()
+        .map(|authority| HeaderValue::from_str(authority.as_str()))
+    {
+        req.headers_mut().insert(HOST, host);
+    }
+
+    let mut res = match proxy.client.request(req).await {
+        Ok(res) => res,
+        Err(err) => {
+            warn!("Dev server at {} is unreachable: {err}", proxy.upstream);
+            return ErrorResponse::new(
+                ErrorReason::ServiceUnavailable,
+                format!("Dev server at {} is unreachable, is it running?", proxy.upstream),
+            )
+            .into_response();
+        }
+    };
+
+    if let (StatusCode::SWITCHING_PROTOCOLS, Some(client_upgrade)) = (res.status(), client_upgrade) {
+        let upstream_upgrade = hyper::upgrade::on(&mut res);
+        tokio::spawn(async move {
+            match tokio::try_join!(client_upgrade, upstream_upgrade) {
+                Ok((client, upstream)) => {
+                    let (mut client, mut upstream) = (TokioIo::new(client), TokioIo::new(upstream));
+                    if let Err(err) = tokio::io::copy_bidirectional(&mut client, &mut upstream).await {
+                        debug!("Dev proxy tunnel closed: {err}");
+                    }
+                }
+                Err(err) => warn!("Dev proxy upgrade failed: {err}"),
+            }
+        });
+    }
+
+    res.map(Body::new)
+}
+
+#[cfg(test)]
+mod test {
+    use axum::http::Uri;
+
+    use super::DevProxy;
+
+    #[test]
+    fn upstream_uri_given_request_path_then_path_and_query_kept() {
+        // Given
+        let proxy = DevProxy::new("http://localhost:3000").unwrap();
+
+        // When
+        let uri = proxy.upstream_uri(&Uri::from_static("/src/App.tsx?t=1")).unwrap();
+
+        // Then
+        assert_eq!(uri, "http://localhost:3000/src/App.tsx?t=1");
+        assert!(DevProxy::new("localhost:3000").is_err());
+    }
+}
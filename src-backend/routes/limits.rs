@@ -0,0 +1,226 @@
+use crate::model::{Config, ErrorReason, ErrorResponse, HandlerOverride, handler_override};
+use axum::{
+    body::{Body, to_bytes},
+    extract::{Request, State},
+    middleware::Next,
+    response::{IntoResponse, Response},
+};
+use serde::Deserialize;
+use std::{sync::Arc, time::Duration};
+use tokio::sync::Semaphore;
+
+/// Body size, timeout and concurrency limits applied to every request
+#[derive(Debug)]
+pub struct RequestLimits {
+    body: usize,
+    body_handlers: Vec<HandlerOverride<usize>>,
+    timeout: Option<Duration>,
+    concurrency: Option<Arc<Semaphore>>,
+}
+
+impl RequestLimits {
+    pub fn new(config: &Config) -> Self {
+        Self {
+            body: config.body_limit,
+            body_handlers: config.body_limit_handlers.clone(),
+            timeout: (config.request_timeout > 0).then(|| Duration::from_secs(config.request_timeout)),
+            concurrency: (config.concurrency_limit > 0).then(|| Arc::new(Semaphore::new(config.concurrency_limit))),
+        }
+    }
+
+    /// Largest body any request may carry, as handler overrides may exceed the global limit
+    fn body_max(&self) -> usize {
+        self.body_handlers.iter().map(|o| o.value).fold(self.body, usize::max)
+    }
+
+    /// Body limit of a JSON-RPC call, the strictest handler limit applying to batches
+    fn body_limit(&self, methods: &[String]) -> usize {
+        methods
+            .iter()
+            .map(|method| handler_override(&self.body_handlers, method).unwrap_or(self.body))
+            .min()
+            .unwrap_or(self.body)
+    }
+}
+
+/// Middleware shedding requests over the concurrency limit, then enforcing body limits and the request timeout.
+/// Every rejection is answered with an [`ErrorResponse`] rather than a dropped connection.
+pub async fn enforce_limits(State(limits): State<Arc<RequestLimits>>, req: Request, next: Next) -> Response {
+    let _permit = match &limits.concurrency {
+        Some(semaphore) => match semaphore.clone().try_acquire_owned() {
+            Ok(permit) => Some(permit),
+            Err(_) => {
+                return ErrorResponse::new(
+                    ErrorReason::ServiceUnavailable,
+                    "Server is overloaded, try again later".into(),
+                )
+                .into_response();
+            }
+        },
+        None => None,
+    };
+
+    let handle = async {
+        let (parts, body) = req.into_parts();
+        let body = match to_bytes(body, limits.body_max()).await {
+            Ok(body) => body,
+            Err(_) => return body_too_large(limits.body_max()),
+        };
+
+        if parts.uri.path().starts_with("/rpc") {
+            let limit = limits.body_limit(&rpc_methods(&body));
+            if body.len() > limit {
+                return body_too_large(limit);
+            }
+        }
+
+        next.run(Request::from_parts(parts, Body::from(body))).await
+    };
+
+    match limits.timeout {
+        Some(timeout) => tokio::time::timeout(timeout, handle).await.unwrap_or_else(|_| {
+            ErrorResponse::new(
+                ErrorReason::GatewayTimeout,
+                format!("Request timed out after {}s", timeout.as_secs()),
+            )
+            .into_response()
+        }),
+        None => handle.await,
+    }
+}
+
+fn body_too_large(limit: usize) -> Response {
+    ErrorResponse::new(ErrorReason::BadRequest, format!("Request body exceeds {limit} bytes")).into_response()
+}
+
+/// JSON-RPC method names of a single or batched call
+fn rpc_methods(body: &[u8]) -> Vec<String> {
+    #[derive(Deserialize)]
+    struct Call {
+        method: String,
+    }
+
+    #[derive(Deserialize)]
+    #[serde(untagged)]
+    enum Calls {
+        Single(Call),
+        Batch(Vec<Call>),
+    }
+
+    match serde_json::from_slice(body) {
+        Ok(Calls::Single(call)) => vec![call.method],
+        Ok(Calls::Batch(calls)) => calls.into_iter().map(|call| call.method).collect(),
+        Err(_) => Vec::new(),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use axum::{
+        Router,
+        body::Body,
+        extract::Request,
+        http::StatusCode,
+        middleware,
+        response::Response,
+        routing::{get, post},
+    };
+    use clap::Parser;
+    use http_body_util::BodyExt;
+    use std::{sync::Arc, time::Duration};
+    use tokio::sync::Notify;
+    use tower::ServiceExt;
+
+    use super::{RequestLimits, enforce_limits, rpc_methods};
+    use crate::model::{Config, ErrorReason, ErrorResponse};
+
+    /// Router behind [`enforce_limits`] configured by the given flags, with `release` ending `/hold` requests
+    fn limited(flags: &[&str], release: Arc<Notify>) -> (Router, Arc<RequestLimits>) {
+        let config = Config::parse_from([env!("CARGO_PKG_NAME")].iter().chain(flags));
+        let limits = Arc::new(RequestLimits::new(&config));
+        let router = Router::new()
+            .route("/echo", post(|body: String| async move { body }))
+            .route("/slow", get(|| tokio::time::sleep(Duration::from_secs(60))))
+            .route("/hold", get(move || async move { release.notified().await }))
+            .layer(middleware::from_fn_with_state(limits.clone(), enforce_limits));
+        (router, limits)
+    }
+
+    async fn error_reason(response: Response) -> (StatusCode, ErrorReason) {
+        let status = response.status();
+        let body = response.into_body().collect().await.unwrap().to_bytes();
+        let err: ErrorResponse = serde_json::from_slice(&body).unwrap();
+        (status, err.reason)
+    }
+
+    #[tokio::test]
+    async fn enforce_limits_given_oversized_body_then_bad_request() {
+        // Given
+        let (router, _) = limited(&["--body-limit", "16"], Arc::default());
+        let request = Request::post("/echo").body(Body::from("x".repeat(17))).unwrap();
+
+        // When
+        let response = router.oneshot(request).await.unwrap();
+
+        // Then
+        assert_eq!(
+            error_reason(response).await,
+            (StatusCode::BAD_REQUEST, ErrorReason::BadRequest)
+        );
+    }
+
+    #[tokio::test]
+    async fn enforce_limits_given_slow_handler_then_gateway_timeout() {
+        // Given
+        let (router, _) = limited(&["--request-timeout", "1"], Arc::default());
+        let request = Request::get("/slow").body(Body::empty()).unwrap();
+
+        // When
+        let response = router.oneshot(request).await.unwrap();
+
+        // Then
+        assert_eq!(
+            error_reason(response).await,
+            (StatusCode::GATEWAY_TIMEOUT, ErrorReason::GatewayTimeout)
+        );
+    }
+
+    #[tokio::test]
+    async fn enforce_limits_given_concurrency_exhausted_then_service_unavailable() {
+        // Given
+        let release = Arc::new(Notify::new());
+        let (router, limits) = limited(&["--concurrency-limit", "1"], release.clone());
+        let held = tokio::spawn(router.clone().oneshot(Request::get("/hold").body(Body::empty()).unwrap()));
+        let semaphore = limits.concurrency.clone().unwrap();
+        while semaphore.available_permits() > 0 {
+            tokio::task::yield_now().await;
+        }
+
+        // When
+        let response = router
+            .oneshot(Request::get("/echo").body(Body::empty()).unwrap())
+            .await
+            .unwrap();
+
+        // Then
+        assert_eq!(
+            error_reason(response).await,
+            (StatusCode::SERVICE_UNAVAILABLE, ErrorReason::ServiceUnavailable)
+        );
+        release.notify_one();
+        assert_eq!(held.await.unwrap().unwrap().status(), StatusCode::OK);
+    }
+
+    #[test]
+    fn rpc_methods_given_batch_then_every_method_returned() {
+        // Given
+        let body = br#"[{"jsonrpc":"2.0","id":1,"method":"register","params":[]},{"jsonrpc":"2.0","id":2,"method":"admin.set_log_filter"}]"#;
+
+        // When
+        let methods = rpc_methods(body);
+
+        // Then
+        assert_eq!(methods, vec!["register".to_string(), "admin.set_log_filter".to_string()]);
+        assert!(rpc_methods(b"not json").is_empty());
+    }
+}
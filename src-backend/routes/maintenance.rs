@@ -0,0 +1,30 @@
+use crate::{logic::Maintenance, model::MaintenanceMode};
+use axum::{
+    extract::{Request, State},
+    http::{
+        HeaderValue, StatusCode,
+        header::{CACHE_CONTROL, RETRY_AFTER},
+    },
+    middleware::Next,
+    response::{Html, IntoResponse, Response},
+};
+use std::sync::Arc;
+
+const MAINTENANCE_PAGE: &str = include_str!("maintenance.html");
+
+/// Middleware serving the maintenance page in place of the SPA during full maintenance.
+/// `/rpc` stays routed so admin RPCs can lift it, other handlers being rejected by `track_handler`,
+/// and `/metrics` keeps monitoring working.
+pub async fn maintenance_page(State(maintenance): State<Arc<Maintenance>>, req: Request, next: Next) -> Response {
+    let is_exempt = req.uri().path().starts_with("/rpc") || req.uri().path() == "/metrics";
+    if maintenance.mode() != MaintenanceMode::Full || is_exempt {
+        return next.run(req).await;
+    }
+
+    let mut response = (StatusCode::SERVICE_UNAVAILABLE, Html(MAINTENANCE_PAGE)).into_response();
+    response.headers_mut().insert(RETRY_AFTER, HeaderValue::from_static("300"));
+    response
+        .headers_mut()
+        .insert(CACHE_CONTROL, HeaderValue::from_static("no-store"));
+    response
+}
@@ -1,47 +1,188 @@
+mod admin;
 mod auth;
+mod dev_proxy;
+mod limits;
+mod maintenance;
+mod prometheus;
+mod rate_limit;
+mod route_manifest;
 mod spa;
 
-use std::path::Path;
+use std::{net::IpAddr, ops::Deref, path::Path, sync::Arc};
 
-use crate::{Config, routes::spa::Spa};
-use axum::routing::Router;
-use axum_embed::FallbackBehavior;
-use qubit::ServerHandle;
-use sqlx::SqlitePool;
-use tower_http::{cors::CorsLayer, trace::TraceLayer};
+use crate::{
+    Config,
+    logic::{
+        Caller, LiveConfig, Maintenance, REQUEST_ID_HEADER, RequestScope, RequestScoping, SetupToken, http_span, scope_request,
+    },
+    model::{ErrorReason, ErrorResponse},
+    routes::spa::{Spa, SpaFallback},
+};
+use axum::{
+    extract::Request,
+    http::Extensions,
+    middleware,
+    routing::{Router, any},
+};
+use qubit::{FromRequestExtensions, RpcError, ServerHandle};
+use sqlx::{Sqlite, SqlitePool, pool::PoolConnection};
+use tower_http::{
+    compression::CompressionLayer,
+    cors::{AllowOrigin, CorsLayer},
+    request_id::{MakeRequestUuid, PropagateRequestIdLayer, SetRequestIdLayer},
+    trace::TraceLayer,
+};
 
 pub struct Routes {
     pub axum: Router,
-    pub auth: ServerHandle,
+    /// Handle the config watcher swaps reloaded settings into
+    pub live: LiveConfig,
+    /// Standalone metrics router, present when metrics are served on their own port
+    pub metrics: Option<Router>,
+    pub rpc: ServerHandle,
 }
 
 #[allow(dead_code)]
 #[derive(Debug, Clone)]
 pub struct Ctx {
     pub config: Config,
-    pub pool: SqlitePool,
+    /// Reached through [`Ctx::acquire`] so every wait for a connection is recorded
+    pool: SqlitePool,
+    /// Settings reloaded from the config file while running
+    pub live: LiveConfig,
+    pub maintenance: Arc<Maintenance>,
+    /// One-time token of the first admin's `setup`
+    pub setup: Arc<SetupToken>,
+}
+
+impl Ctx {
+    pub fn new(config: Config, pool: SqlitePool) -> Self {
+        let live = LiveConfig::new(config.clone());
+        let maintenance = Arc::new(Maintenance::new(config.maintenance));
+        Self {
+            config,
+            pool,
+            live,
+            maintenance,
+            setup: Arc::default(),
+        }
+    }
+
+    /// Acquires a pooled connection, tracking the wait time in metrics
+    pub async fn acquire(&self) -> Result<PoolConnection<Sqlite>, sqlx::Error> {
+        crate::logic::acquire(&self.pool).await
+    }
+}
+
+/// Ctx of a qubit handler together with the [`RequestScope`] of the request it serves
+#[derive(Debug, Clone)]
+pub struct RequestCtx {
+    pub ctx: Ctx,
+    pub request: RequestScope,
+}
+
+impl FromRequestExtensions<Ctx> for RequestCtx {
+    async fn from_request_extensions(ctx: Ctx, extensions: Extensions) -> Result<Self, RpcError> {
+        let request = extensions.get::<RequestScope>().cloned().unwrap_or_default();
+        Ok(RequestCtx { ctx, request })
+    }
+}
+
+impl Deref for RequestCtx {
+    type Target = Ctx;
+
+    fn deref(&self) -> &Ctx {
+        &self.ctx
+    }
+}
+
+impl RequestCtx {
+    /// Client IP of the request, resolved through trusted proxies
+    pub fn client_ip(&self) -> Option<IpAddr> {
+        self.request.client_ip
+    }
+
+    /// Scheme (`http` or `https`) the client used, if forwarded by a trusted proxy
+    pub fn scheme(&self) -> Option<&str> {
+        self.request.scheme.as_deref()
+    }
+
+    /// Checks the caller's bearer token was the configured `admin_token`
+    pub fn authorize_admin(&self) -> Result<(), ErrorResponse> {
+        match (&self.config.admin_token, &self.request.caller) {
+            (None, _) => Err(ErrorResponse::new(ErrorReason::Forbidden, "Admin API is disabled".into())),
+            (Some(_), Caller::Admin) => Ok(()),
+            _ => Err(ErrorResponse::new(ErrorReason::Unauthorized, "Invalid admin token".into())),
+        }
+    }
 }
 
 impl Routes {
-    pub fn build(config: Config, pool: SqlitePool) -> crate::Result<Self> {
-        let is_cors = config.server_cors;
-        let ctx = Ctx { config, pool };
-        let (auth_service, auth) = auth::router().to_service(ctx);
-
-        let mut axum = axum::Router::<()>::new()
-            .nest_service("/rpc", auth_service)
-            .route_service(
+    pub fn build(config: Config, pool: SqlitePool, setup: SetupToken) -> crate::Result<Self> {
+        let base_path = config.base_path();
+        let (is_metrics, is_metrics_standalone) = (config.metrics_enabled, config.metrics_port.is_some());
+        let metrics_router = prometheus::router(pool.clone());
+        let scoping = Arc::new(RequestScoping::new(&config, pool.clone()));
+        let ctx = Ctx {
+            setup: Arc::new(setup),
+            ..Ctx::new(config, pool)
+        };
+        let live = ctx.live.clone();
+        let maintenance = ctx.maintenance.clone();
+        let request_limits = Arc::new(limits::RequestLimits::new(&ctx.config));
+        let dev_proxy = ctx
+            .config
+            .server_dev_proxy
+            .as_deref()
+            .map(dev_proxy::DevProxy::new)
+            .transpose()?;
+        let (rpc_service, rpc) = Self::rpc_router().to_service(ctx);
+
+        let mut axum = axum::Router::<()>::new().nest_service("/rpc", rpc_service);
+        if dev_proxy.is_none() {
+            axum = axum.route_service(
                 "/assets/{*files}",
-                Spa::service(FallbackBehavior::NotFound), // Avoids serving spa when asset not found
-            )
-            .layer(TraceLayer::new_for_http())
-            .fallback_service(Spa::service(FallbackBehavior::Ok));
+                Spa::service(SpaFallback::NotFound, &base_path), // Avoids serving spa when asset not found
+            );
+        }
+
+        let mut metrics = None;
+        if is_metrics && is_metrics_standalone {
+            metrics = Some(metrics_router);
+        } else if is_metrics {
+            axum = axum.merge(metrics_router);
+        }
+
+        let mut axum = axum
+            .fallback_service(match dev_proxy {
+                Some(proxy) => any(dev_proxy::proxy).with_state(proxy),
+                None => Spa::service(SpaFallback::Index, &base_path),
+            })
+            .layer(middleware::from_fn_with_state(maintenance, maintenance::maintenance_page))
+            .layer(middleware::from_fn_with_state(live.clone(), rate_limit::limit_http))
+            .layer(middleware::from_fn_with_state(request_limits, limits::enforce_limits))
+            .layer(middleware::from_fn(prometheus::track_http))
+            .layer(middleware::from_fn_with_state(scoping, scope_request))
+            .layer(TraceLayer::new_for_http().make_span_with(|req: &Request<_>| http_span(req)))
+            .layer(PropagateRequestIdLayer::new(REQUEST_ID_HEADER.clone()))
+            .layer(SetRequestIdLayer::new(REQUEST_ID_HEADER.clone(), MakeRequestUuid))
+            .layer(CompressionLayer::new()); // Skips responses already encoded, i.e. precompressed assets
 
-        if is_cors {
-            axum = axum.layer(CorsLayer::permissive());
+        if base_path != "/" {
+            axum = axum::Router::new().nest(base_path.trim_end_matches('/'), axum);
         }
 
-        Ok(Self { axum, auth })
+        // Installed regardless of `server_cors` so toggling it applies live
+        let cors_live = live.clone();
+        axum = axum
+            .layer(CorsLayer::permissive().allow_origin(AllowOrigin::predicate(move |_, _| cors_live.load().config.server_cors)));
+
+        Ok(Self {
+            axum,
+            live,
+            metrics,
+            rpc,
+        })
     }
 
     pub fn axum(&self) -> Router {
@@ -49,11 +190,102 @@ impl Routes {
     }
 
     pub fn stop_services(self) -> crate::Result {
-        self.auth.stop()?;
+        self.rpc.stop()?;
         Ok(())
     }
 
+    /// Whether the SPA build was embedded, i.e. the frontend was built before the backend
+    pub fn is_spa_embedded() -> bool {
+        Spa::get("index.html").is_some()
+    }
+
     pub fn gen_bindings(bindings_dir: &Path) {
-        auth::router().write_bindings_to_dir(bindings_dir);
+        Self::rpc_router().write_bindings_to_dir(bindings_dir);
+    }
+
+    fn rpc_router() -> qubit::Router<Ctx> {
+        auth::router().nest("admin", admin::router())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use axum::{body::Body, extract::ConnectInfo, http::Request, middleware};
+    use clap::Parser;
+    use http_body_util::BodyExt;
+    use qubit::handler;
+    use serde_json::Value;
+    use sqlx::SqlitePool;
+    use std::{net::SocketAddr, sync::Arc};
+    use tower::ServiceExt;
+
+    use super::{Ctx, RequestCtx, Routes};
+    use crate::{
+        logic::{RequestScoping, SetupToken, TrustedProxies, scope_request},
+        model::Config,
+    };
+
+    #[handler(query)]
+    async fn client_ip(ctx: RequestCtx) -> Option<String> {
+        ctx.client_ip().map(|ip| ip.to_string())
+    }
+
+    #[tokio::test]
+    async fn request_ctx_given_trusted_proxy_then_handler_sees_forwarded_ip() {
+        // Given
+        let ctx = Ctx::new(
+            Config::parse_from([env!("CARGO_PKG_NAME")]),
+            SqlitePool::connect_lazy("sqlite::memory:").unwrap(),
+        );
+        let (rpc_service, _rpc) = qubit::Router::<Ctx>::new().handler(client_ip).to_service(ctx);
+        let scoping = Arc::new(RequestScoping {
+            proxies: TrustedProxies::new(vec!["127.0.0.1/32".parse().unwrap()]),
+            admin_token: None,
+            pool: SqlitePool::connect_lazy("sqlite::memory:").unwrap(),
+        });
+        let app = axum::Router::new()
+            .nest_service("/rpc", rpc_service)
+            .layer(middleware::from_fn_with_state(scoping, scope_request));
+        let request = Request::post("/rpc")
+            .header("content-type", "application/json")
+            .header("x-forwarded-for", "203.0.113.7")
+            .extension(ConnectInfo(SocketAddr::from(([127, 0, 0, 1], 4000))))
+            .body(Body::from(r#"{"jsonrpc":"2.0","id":1,"method":"client_ip","params":[]}"#))
+            .unwrap();
+
+        // When
+        let response = app.oneshot(request).await.unwrap();
+
+        // Then
+        let body = response.into_body().collect().await.unwrap().to_bytes();
+        let body: Value = serde_json::from_slice(&body).unwrap();
+        assert_eq!(body["result"], "203.0.113.7");
+    }
+
+    #[tokio::test]
+    async fn routes_given_failing_rpc_then_error_carries_response_request_id() {
+        // Given
+        let routes = Routes::build(
+            Config::parse_from([env!("CARGO_PKG_NAME")]),
+            SqlitePool::connect_lazy("sqlite::memory:").unwrap(),
+            SetupToken::default(),
+        )
+        .unwrap();
+        let request = Request::post("/rpc")
+            .header("content-type", "application/json")
+            .body(Body::from(
+                r#"{"jsonrpc":"2.0","id":1,"method":"admin.set_maintenance","params":["read_only"]}"#,
+            ))
+            .unwrap();
+
+        // When
+        let response = routes.axum().oneshot(request).await.unwrap();
+
+        // Then
+        let request_id = response.headers()["x-request-id"].to_str().unwrap().to_string();
+        let body = response.into_body().collect().await.unwrap().to_bytes();
+        let body: Value = serde_json::from_slice(&body).unwrap();
+        assert_eq!(body["result"]["Err"]["reason"], "Forbidden");
+        assert_eq!(body["result"]["Err"]["request_id"], request_id.as_str());
     }
 }
@@ -0,0 +1,78 @@
+use crate::logic::{record_http, render_metrics};
+use axum::{
+    Router,
+    extract::{MatchedPath, Request, State},
+    http::StatusCode,
+    middleware::Next,
+    response::{IntoResponse, Response},
+    routing::get,
+};
+use sqlx::SqlitePool;
+use std::time::Instant;
+
+/// Router exposing the Prometheus scrape endpoint at `/metrics`
+pub fn router(pool: SqlitePool) -> Router {
+    Router::new().route("/metrics", get(scrape)).with_state(pool)
+}
+
+async fn scrape(State(pool): State<SqlitePool>) -> Response {
+    match render_metrics(&pool) {
+        Some(body) => body.into_response(),
+        None => StatusCode::NOT_FOUND.into_response(),
+    }
+}
+
+/// Middleware recording request counts and latency per matched route
+pub async fn track_http(req: Request, next: Next) -> Response {
+    let started = Instant::now();
+    let method = req.method().to_string();
+    let route = req
+        .extensions()
+        .get::<MatchedPath>()
+        .map(|path| path.as_str().to_string())
+        .unwrap_or_else(|| "fallback".to_string());
+
+    let response = next.run(req).await;
+    record_http(&method, &route, response.status().as_u16(), started);
+    response
+}
+
+#[cfg(test)]
+mod test {
+    use axum::{Router, body::Body, extract::Request, middleware, routing::get};
+    use clap::Parser;
+    use http_body_util::BodyExt;
+    use sqlx::SqlitePool;
+    use tower::ServiceExt;
+
+    use super::{router, track_http};
+    use crate::{logic::install_metrics, model::Config};
+
+    #[tokio::test]
+    async fn track_http_given_request_then_counted_and_timed_in_scrape() {
+        // Given
+        install_metrics(&Config::parse_from([env!("CARGO_PKG_NAME"), "--metrics-enabled"])).unwrap();
+        let app = Router::new()
+            .route("/hello", get(|| async { "hello" }))
+            .merge(router(SqlitePool::connect_lazy("sqlite::memory:").unwrap()))
+            .layer(middleware::from_fn(track_http));
+
+        // When
+        app.clone()
+            .oneshot(Request::get("/hello").body(Body::empty()).unwrap())
+            .await
+            .unwrap();
+        let scrape = app
+            .oneshot(Request::get("/metrics").body(Body::empty()).unwrap())
+            .await
+            .unwrap();
+
+        // Then
+        let body = scrape.into_body().collect().await.unwrap().to_bytes();
+        let body = String::from_utf8(body.to_vec()).unwrap();
+        assert!(body.contains(r#"http_requests_total{method="GET",route="/hello",status="200"} 1"#));
+        assert!(body.contains(r#"http_request_duration_seconds_bucket{method="GET",route="/hello",status="200",le="0.001"}"#));
+        assert!(body.contains(r#"http_request_duration_seconds_count{method="GET",route="/hello",status="200"} 1"#));
+        assert!(body.contains("db_pool_max"));
+    }
+}
@@ -0,0 +1,50 @@
+use crate::{
+    logic::{LiveConfig, RateLimitStatus, RequestScope},
+    model::ErrorResponse,
+};
+use axum::{
+    extract::{Request, State},
+    http::{HeaderMap, HeaderName, HeaderValue},
+    middleware::Next,
+    response::{IntoResponse, Response},
+};
+
+static RATELIMIT_LIMIT: HeaderName = HeaderName::from_static("ratelimit-limit");
+static RATELIMIT_REMAINING: HeaderName = HeaderName::from_static("ratelimit-remaining");
+static RATELIMIT_RESET: HeaderName = HeaderName::from_static("ratelimit-reset");
+
+/// Middleware enforcing the router-wide quota, setting the `RateLimit-*` headers on every response
+pub async fn limit_http(State(live): State<LiveConfig>, req: Request, next: Next) -> Response {
+    let limits = live.load().rate_limits.clone();
+    let Some(check) = req
+        .extensions()
+        .get::<RequestScope>()
+        .and_then(|scope| limits.check_http(scope))
+    else {
+        return next.run(req).await;
+    };
+
+    match check {
+        Ok(status) => {
+            let mut response = next.run(req).await;
+            insert_headers(response.headers_mut(), status);
+            response
+        }
+        Err(limited) => {
+            let mut response = ErrorResponse::from(limited).into_response();
+            let status = RateLimitStatus {
+                limit: limited.limit,
+                remaining: 0,
+                reset_secs: limited.retry_after_secs,
+            };
+            insert_headers(response.headers_mut(), status);
+            response
+        }
+    }
+}
+
+fn insert_headers(headers: &mut HeaderMap, status: RateLimitStatus) {
+    headers.insert(RATELIMIT_LIMIT.clone(), HeaderValue::from(status.limit));
+    headers.insert(RATELIMIT_REMAINING.clone(), HeaderValue::from(status.remaining));
+    headers.insert(RATELIMIT_RESET.clone(), HeaderValue::from(status.reset_secs));
+}
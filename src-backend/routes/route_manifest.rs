@@ -0,0 +1,83 @@
+use serde::Deserialize;
+
+/// Segment of a client route pattern, following the frontend router's syntax
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Segment {
+    Static(String),
+    /// `:name`, or `:name?` when optional
+    Param {
+        optional: bool,
+    },
+    /// `*` or `*name`, matching any remainder
+    Wildcard,
+}
+
+/// Client route patterns emitted by the frontend build as `routes.json` (e.g. `["/", "/users/:id", "/docs/*"]`)
+#[derive(Debug, Clone, Deserialize)]
+#[serde(from = "Vec<String>")]
+pub struct RouteManifest(Vec<Vec<Segment>>);
+
+impl From<Vec<String>> for RouteManifest {
+    fn from(patterns: Vec<String>) -> Self {
+        let parse = |pattern: &str| -> Vec<Segment> {
+            segments(pattern)
+                .map(|segment| match segment.chars().next() {
+                    Some(':') => Segment::Param {
+                        optional: segment.ends_with('?'),
+                    },
+                    Some('*') => Segment::Wildcard,
+                    _ => Segment::Static(segment.to_string()),
+                })
+                .collect()
+        };
+        Self(patterns.iter().map(|pattern| parse(pattern)).collect())
+    }
+}
+
+impl RouteManifest {
+    pub const FILE: &str = "routes.json";
+
+    /// Whether a client route handles the path
+    pub fn matches(&self, path: &str) -> bool {
+        let path: Vec<&str> = segments(path).collect();
+        self.0.iter().any(|pattern| matches(pattern, &path))
+    }
+}
+
+fn segments(path: &str) -> impl Iterator<Item = &str> {
+    path.split('/').filter(|segment| !segment.is_empty())
+}
+
+fn matches(pattern: &[Segment], path: &[&str]) -> bool {
+    match (pattern.split_first(), path.split_first()) {
+        (None, None) => true,
+        (Some((Segment::Wildcard, _)), _) => true,
+        (Some((Segment::Param { optional: true }, rest)), _) if matches(rest, path) => true,
+        (Some((Segment::Param { .. }, rest)), Some((_, path))) => matches(rest, path),
+        (Some((Segment::Static(expected), rest)), Some((segment, path))) => expected == segment && matches(rest, path),
+        _ => false,
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::RouteManifest;
+
+    #[test]
+    fn matches_given_patterns_then_only_client_routes_match() {
+        // Given
+        let manifest = RouteManifest::from(vec![
+            "/".to_string(),
+            "/users/:id/:tab?".to_string(),
+            "/docs/*rest".to_string(),
+        ]);
+
+        // When
+        let known = ["/", "/users/1", "/users/1/settings/", "/docs", "/docs/a/b"];
+        let unknown = ["/users", "/users/1/settings/extra", "/missing"];
+
+        // Then
+        assert!(known.iter().all(|path| manifest.matches(path)));
+        assert!(!unknown.iter().any(|path| manifest.matches(path)));
+    }
+}
@@ -1,26 +1,326 @@
-use axum_embed::{FallbackBehavior, ServeEmbed};
-use rust_embed::RustEmbed;
-use tracing::trace;
+use axum::{
+    body::Body,
+    http::{
+        HeaderMap, HeaderValue, StatusCode, Uri,
+        header::{ACCEPT_ENCODING, CACHE_CONTROL, CONTENT_ENCODING, CONTENT_TYPE, ETAG, IF_NONE_MATCH, VARY},
+    },
+    response::{IntoResponse, Response},
+    routing::{MethodRouter, get},
+};
+use rust_embed::{EmbeddedFile, RustEmbed};
+use std::{fmt::Write, sync::Arc};
+use tracing::{trace, warn};
+
+use crate::routes::route_manifest::RouteManifest;
+
+/// Fingerprinted by the bundler, so their content never changes under the same path
+const IMMUTABLE_PREFIX: &str = "assets/";
 
 #[derive(RustEmbed, Clone)]
 #[folder = "dist/"]
 pub struct Spa;
 
-impl Spa {
-    pub(crate) fn service(fallback: FallbackBehavior) -> ServeEmbed<Spa> {
-        let service = match fallback {
-            FallbackBehavior::NotFound => ServeEmbed::<Spa>::with_parameters(None, fallback, None),
-            _ => ServeEmbed::<Spa>::with_parameters(
-                Some("/".to_string()),
-                fallback,
-                Some("index.html".to_string()),
-            ),
-        };
+/// What to serve when a path is not an embedded file
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SpaFallback {
+    NotFound,
+    /// Serve `index.html`, letting the client side router handle the path.
+    /// Paths matching no route of the frontend's `routes.json` get it with a `404` status.
+    Index,
+}
+
+/// Settings shared by every request to a [`Spa::service`]
+#[derive(Debug)]
+struct SpaOptions {
+    fallback: SpaFallback,
+    base_path: String,
+    /// Absent when the frontend build emitted no manifest, treating every path as a client route
+    routes: Option<RouteManifest>,
+}
+
+/// Precompressed variants written next to each asset by `build.rs`, in order of preference
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Encoding {
+    Brotli,
+    Gzip,
+}
+
+impl Encoding {
+    const PREFERRED: [Encoding; 2] = [Encoding::Brotli, Encoding::Gzip];
 
+    fn token(self) -> &'static str {
+        match self {
+            Encoding::Brotli => "br",
+            Encoding::Gzip => "gzip",
+        }
+    }
+
+    fn extension(self) -> &'static str {
+        match self {
+            Encoding::Brotli => "br",
+            Encoding::Gzip => "gz",
+        }
+    }
+}
+
+impl Spa {
+    /// Serves embedded files, rebasing HTML pages onto `base_path` (e.g. `/breezi/`)
+    pub(crate) fn service(fallback: SpaFallback, base_path: &str) -> MethodRouter {
         for file in Spa::iter() {
             trace!("Router's embedded asset: /{}", file.as_ref());
         }
 
-        service
+        let options = Arc::new(SpaOptions {
+            fallback,
+            base_path: base_path.to_string(),
+            routes: Self::routes(),
+        });
+        get(move |uri: Uri, headers: HeaderMap| async move { Self::serve(uri.path(), &headers, &options) })
+    }
+
+    /// Client routes from the embedded manifest
+    fn routes() -> Option<RouteManifest> {
+        let manifest = Spa::get(RouteManifest::FILE)?;
+        serde_json::from_slice(&manifest.data)
+            .inspect_err(|err| warn!("Ignoring invalid {}: {err}", RouteManifest::FILE))
+            .ok()
+    }
+
+    fn serve(uri_path: &str, headers: &HeaderMap, options: &SpaOptions) -> Response {
+        let path = uri_path.trim_start_matches('/');
+        let path = match path.is_empty() || path.ends_with('/') {
+            true => format!("{path}index.html"),
+            false => path.to_string(),
+        };
+
+        if let Some(response) = Self::file(&path, headers, &options.base_path) {
+            return response;
+        }
+        if options.fallback == SpaFallback::NotFound {
+            return StatusCode::NOT_FOUND.into_response();
+        }
+
+        let is_client_route = options.routes.as_ref().is_none_or(|routes| routes.matches(uri_path));
+        let mut headers = headers.clone();
+        if !is_client_route {
+            headers.remove(IF_NONE_MATCH); // The shell is sent in full with the 404
+        }
+        let mut response =
+            Self::file("index.html", &headers, &options.base_path).unwrap_or_else(|| StatusCode::NOT_FOUND.into_response());
+        if !is_client_route {
+            *response.status_mut() = StatusCode::NOT_FOUND;
+        }
+        response
+    }
+
+    /// Serves an embedded file, using its best precompressed variant the client accepts.
+    /// Answers `304 Not Modified` when the client's `If-None-Match` holds the variant's ETag.
+    fn file(path: &str, headers: &HeaderMap, base_path: &str) -> Option<Response> {
+        if Self::is_precompressed_variant(path) {
+            return None;
+        }
+        let file = Spa::get(path)?;
+        if path.ends_with(".html") {
+            return Some(Self::html(path, file, headers, base_path));
+        }
+
+        let accepted = accepted_encodings(headers);
+        let (encoding, variant) = Encoding::PREFERRED
+            .into_iter()
+            .filter(|encoding| accepted.contains(encoding))
+            .find_map(|encoding| Some((Some(encoding), Spa::get(&format!("{path}.{}", encoding.extension()))?)))
+            .unwrap_or((None, file.clone()));
+        let etag = etag(&variant.metadata.sha256_hash());
+
+        let mut response = match is_etag_matching(headers, &etag) {
+            true => StatusCode::NOT_MODIFIED.into_response(),
+            false => Body::from(variant.data).into_response(),
+        };
+        Self::insert_headers(&mut response, path, &file, &etag);
+        response
+            .headers_mut()
+            .insert(VARY, HeaderValue::from_static("accept-encoding"));
+        if let Some(encoding) = encoding {
+            response
+                .headers_mut()
+                .insert(CONTENT_ENCODING, HeaderValue::from_static(encoding.token()));
+        }
+        Some(response)
+    }
+
+    /// Serves an HTML page rebased onto `base_path`, left to the compression layer as it differs per deployment
+    fn html(path: &str, file: EmbeddedFile, headers: &HeaderMap, base_path: &str) -> Response {
+        let etag = format!("{}{base_path}\"", etag(&file.metadata.sha256_hash()).trim_end_matches('"'));
+        let mut response = match is_etag_matching(headers, &etag) {
+            true => StatusCode::NOT_MODIFIED.into_response(),
+            false => rebase_html(&String::from_utf8_lossy(&file.data), base_path).into_response(),
+        };
+        Self::insert_headers(&mut response, path, &file, &etag);
+        response
+    }
+
+    /// Whether the path is a `.br` or `.gz` variant written by `build.rs`, only served in place of its original
+    fn is_precompressed_variant(path: &str) -> bool {
+        Encoding::PREFERRED.into_iter().any(|encoding| {
+            path.strip_suffix(encoding.extension())
+                .and_then(|original| original.strip_suffix('.'))
+                .is_some_and(|original| Spa::get(original).is_some())
+        })
+    }
+
+    fn insert_headers(response: &mut Response, path: &str, file: &EmbeddedFile, etag: &str) {
+        let response_headers = response.headers_mut();
+        if let Ok(mime) = HeaderValue::from_str(file.metadata.mimetype()) {
+            response_headers.insert(CONTENT_TYPE, mime);
+        }
+        if let Ok(etag) = HeaderValue::from_str(etag) {
+            response_headers.insert(ETAG, etag);
+        }
+        response_headers.insert(CACHE_CONTROL, cache_control(path));
+    }
+}
+
+/// Points root-relative `src` and `href` URLs at `base_path` and declares it as `<base href>`,
+/// so a `dist/` built for `/` works under any prefix
+fn rebase_html(html: &str, base_path: &str) -> String {
+    let mut html = html.to_string();
+    if base_path != "/" {
+        for attribute in ["src", "href"] {
+            for quote in ['"', '\''] {
+                let root_relative = format!("{attribute}={quote}/");
+                let mut rebased = String::with_capacity(html.len());
+                let mut rest = html.as_str();
+                while let Some(index) = rest.find(&root_relative) {
+                    let (before, after) = rest.split_at(index + root_relative.len());
+                    rebased.push_str(before);
+                    if !after.starts_with('/') {
+                        // Protocol-relative `//host` URLs are left untouched
+                        rebased.push_str(&base_path[1..]);
+                    }
+                    rest = after;
+                }
+                rebased.push_str(rest);
+                html = rebased;
+            }
+        }
+    }
+
+    let base = format!("<base href=\"{base_path}\" />");
+    if let Some(index) = html.find("<head>").filter(|_| !html.contains("<base ")) {
+        html.insert_str(index + "<head>".len(), &base);
+    }
+    html
+}
+
+/// Strong ETag from the content hash computed when embedding
+fn etag(hash: &[u8]) -> String {
+    let hex = hash.iter().fold(String::new(), |mut hex, byte| {
+        let _ = write!(hex, "{byte:02x}");
+        hex
+    });
+    format!("\"{hex}\"")
+}
+
+/// Whether `If-None-Match` lists the ETag, or is `*`
+fn is_etag_matching(headers: &HeaderMap, etag: &str) -> bool {
+    headers
+        .get_all(IF_NONE_MATCH)
+        .iter()
+        .filter_map(|value| value.to_str().ok())
+        .flat_map(|value| value.split(','))
+        .map(|tag| tag.trim().trim_start_matches("W/"))
+        .any(|tag| tag == etag || tag == "*")
+}
+
+/// Fingerprinted assets are cached forever, anything else (i.e. `index.html`) is revalidated so deploys apply instantly
+fn cache_control(path: &str) -> HeaderValue {
+    match path.starts_with(IMMUTABLE_PREFIX) {
+        true => HeaderValue::from_static("public, max-age=31536000, immutable"),
+        false => HeaderValue::from_static("no-cache"),
+    }
+}
+
+/// Encodings listed in `Accept-Encoding`, ignoring any refused with `q=0`
+fn accepted_encodings(headers: &HeaderMap) -> Vec<Encoding> {
+    headers
+        .get_all(ACCEPT_ENCODING)
+        .iter()
+        .filter_map(|value| value.to_str().ok())
+        .flat_map(|value| value.split(','))
+        .flat_map(|item| {
+            let mut parts = item.split(';').map(str::trim);
+            let token = parts.next().unwrap_or_default();
+            let refused = parts.any(|param| matches!(param.strip_prefix("q="), Some(q) if q.parse::<f32>() == Ok(0.0)));
+            Encoding::PREFERRED
+                .into_iter()
+                .filter(move |encoding| !refused && (token == encoding.token() || token == "*"))
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod test {
+    use axum::http::{
+        HeaderMap, HeaderValue,
+        header::{ACCEPT_ENCODING, IF_NONE_MATCH},
+    };
+
+    use super::{Encoding, accepted_encodings, cache_control, etag, is_etag_matching, rebase_html};
+
+    #[test]
+    fn accepted_encodings_given_refused_brotli_then_only_gzip() {
+        // Given
+        let mut headers = HeaderMap::new();
+        headers.insert(ACCEPT_ENCODING, HeaderValue::from_static("gzip, deflate, br;q=0"));
+
+        // When
+        let accepted = accepted_encodings(&headers);
+
+        // Then
+        assert_eq!(accepted, vec![Encoding::Gzip]);
+    }
+
+    #[test]
+    fn is_etag_matching_given_etag_listed_then_matching() {
+        // Given
+        let etag = etag(&[0xab, 0x01]);
+        let mut headers = HeaderMap::new();
+        headers.insert(IF_NONE_MATCH, HeaderValue::from_static("\"other\", W/\"ab01\""));
+
+        // When
+        let is_matching = is_etag_matching(&headers, &etag);
+
+        // Then
+        assert_eq!(etag, "\"ab01\"");
+        assert!(is_matching);
+        assert!(!is_etag_matching(&HeaderMap::new(), &etag));
+    }
+
+    #[test]
+    fn cache_control_given_hashed_asset_then_immutable_and_html_revalidated() {
+        // Given
+        let (html, asset) = ("index.html", "assets/index-1a2b.js");
+
+        // When
+        let (html, asset) = (cache_control(html), cache_control(asset));
+
+        // Then
+        assert_eq!(html, "no-cache");
+        assert_eq!(asset, "public, max-age=31536000, immutable");
+    }
+
+    #[test]
+    fn rebase_html_given_base_path_then_root_relative_urls_prefixed() {
+        // Given
+        let html = r#"<html><head><script src="/assets/index.js"></script><link href='//cdn.example/x.css'></head></html>"#;
+
+        // When
+        let rebased = rebase_html(html, "/breezi/");
+
+        // Then
+        assert_eq!(
+            rebased,
+            r#"<html><head><base href="/breezi/" /><script src="/breezi/assets/index.js"></script><link href='//cdn.example/x.css'></head></html>"#
+        );
     }
 }
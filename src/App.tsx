@@ -10,9 +10,8 @@ import * as v from "valibot";
 import { createForm, valiForm } from "@modular-forms/solid";
 import { ModularFormField } from "./components/form/field";
 
-const rpcUrl = import.meta.env.DEV
-  ? "http://localhost:8080/rpc"
-  : window.location.origin + "/rpc";
+// `./` in builds, resolved against the server injected `<base href>`, and `/` under the Vite dev server which has none
+const rpcUrl = new URL(`${import.meta.env.BASE_URL}rpc`, document.baseURI).href;
 const api = build_client<QubitServer>(http(rpcUrl));
 
 async function handleRegister() {
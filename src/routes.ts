@@ -0,0 +1,5 @@
+/**
+ * Client route patterns (`/users/:id`, `/users/:id/:tab?`, `/docs/*rest`).
+ * Emitted as `routes.json` by the build so the server answers unknown paths with a 404.
+ */
+export const routes: string[] = ["/"];
@@ -4,12 +4,14 @@ import tailwindcss from "@tailwindcss/vite";
 import path from "path";
 import { spawn } from "child_process";
 import * as fs from "fs";
+import { routes } from "./src/routes";
 
 export default defineConfig({
-  plugins: [solid(), tailwindcss(), valibotBindings()],
+  plugins: [solid(), tailwindcss(), valibotBindings(), routeManifest()],
   server: {
     port: 3000,
   },
+  base: "./", // Relative asset URLs, the server injects `<base href>` for its base path
   build: {
     target: "esnext",
   },
@@ -20,6 +22,19 @@ export default defineConfig({
   },
 });
 
+function routeManifest() {
+  return {
+    name: "route-manifest",
+    generateBundle() {
+      this.emitFile({
+        type: "asset",
+        fileName: "routes.json",
+        source: JSON.stringify(routes),
+      });
+    },
+  };
+}
+
 function valibotBindings() {
   return {
     name: "watch-and-run",